@@ -0,0 +1,521 @@
+//! Cellular automata on a rectangular grid, where each step every cell's next
+//! state depends on its own state and how many of its neighbours are live.
+//!
+//! Which cells count as neighbours and what the counts do are separate: a
+//! `Neighbourhood` picks the cells and a `Rule` turns the count into the next
+//! state, so the same grid can be run with different rules.
+
+use std::fmt;
+use std::mem;
+use std::ops::{Range, RangeInclusive};
+
+/// Cells stored row by row, addressed as `(x, y)` from the top left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<S> {
+    cells: Vec<S>,
+    rows: usize,
+    cols: usize,
+}
+
+impl<S: Copy> Grid<S> {
+    /// A grid from its rows, which all need to be the same length.
+    pub fn new(rows: Vec<Vec<S>>) -> Result<Grid<S>, String> {
+        let cols = rows.first().map_or(0, Vec::len);
+        if let Some((y, row)) = rows.iter().enumerate().find(|(_, row)| row.len() != cols) {
+            return Err(format!(
+                "Row {} has {} cells, but row 0 has {}",
+                y,
+                row.len(),
+                cols
+            ));
+        }
+        Ok(Grid {
+            rows: rows.len(),
+            cols,
+            cells: rows.into_iter().flatten().collect(),
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> S {
+        self.cells[y * self.cols + x]
+    }
+
+    /// The cell one step in direction `(dx, dy)`, if that's still on the grid.
+    fn step_from(&self, x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+        let x = x as isize + dx;
+        let y = y as isize + dy;
+        if x < 0 || y < 0 || x >= self.cols as isize || y >= self.rows as isize {
+            None
+        } else {
+            Some((x as usize, y as usize))
+        }
+    }
+}
+
+impl<S: Copy + PartialEq> Grid<S> {
+    /// How many cells are in `state`.
+    pub fn count(&self, state: S) -> usize {
+        self.cells.iter().filter(|&&cell| cell == state).count()
+    }
+}
+
+impl<S: fmt::Display> fmt::Display for Grid<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (y, row) in self.cells.chunks(self.cols.max(1)).enumerate() {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            for cell in row {
+                write!(f, "{}", cell)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// The neighbours of the cell at `(x, y)` on the grid.
+pub type Neighbours<S> = Box<dyn Fn(&Grid<S>, usize, usize) -> Vec<(usize, usize)>>;
+
+/// Which cells a cell looks at to decide its next state.
+pub enum Neighbourhood<S> {
+    /// The up to eight cells touching it, diagonals included.
+    Adjacent,
+    /// The first cell in each of the eight directions that can't be seen
+    /// through, according to the function.
+    LineOfSight(fn(S) -> bool),
+    /// Anything else.
+    Custom(Neighbours<S>),
+}
+
+impl<S: Copy> Neighbourhood<S> {
+    pub fn neighbours(&self, grid: &Grid<S>, x: usize, y: usize) -> Vec<(usize, usize)> {
+        match self {
+            Neighbourhood::Adjacent => DIRECTIONS
+                .iter()
+                .filter_map(|&(dx, dy)| grid.step_from(x, y, dx, dy))
+                .collect(),
+            Neighbourhood::LineOfSight(see_through) => DIRECTIONS
+                .iter()
+                .filter_map(|&(dx, dy)| {
+                    let mut cell = grid.step_from(x, y, dx, dy)?;
+                    while see_through(grid.get(cell.0, cell.1)) {
+                        cell = grid.step_from(cell.0, cell.1, dx, dy)?;
+                    }
+                    Some(cell)
+                })
+                .collect(),
+            Neighbourhood::Custom(neighbours) => neighbours(grid, x, y),
+        }
+    }
+}
+
+/// How cells switch between a live and a dead state, by how many of their
+/// neighbours are live. Cells in any other state never change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule<S> {
+    pub live: S,
+    pub dead: S,
+    /// Live neighbour counts that bring a dead cell to life.
+    pub birth: RangeInclusive<usize>,
+    /// Live neighbour counts that keep a live cell alive.
+    pub survival: RangeInclusive<usize>,
+}
+
+impl<S: Copy + PartialEq> Rule<S> {
+    /// Whether the rule can change a cell in this state.
+    pub fn applies_to(&self, state: S) -> bool {
+        state == self.live || state == self.dead
+    }
+
+    pub fn next(&self, state: S, live_neighbours: usize) -> S {
+        if state == self.dead && self.birth.contains(&live_neighbours) {
+            self.live
+        } else if state == self.live && !self.survival.contains(&live_neighbours) {
+            self.dead
+        } else {
+            state
+        }
+    }
+}
+
+/// A run that never settles: the grid went back to a state it had been in
+/// before, so it will keep going round the same states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    /// The steps taken before the repeat was noticed.
+    pub steps: usize,
+    /// The steps between repeats of the same state.
+    pub period: usize,
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Never stable: after {} steps, the grid repeats every {} steps",
+            self.steps, self.period
+        )
+    }
+}
+
+/// A cell the rule can change, and where its neighbours are listed.
+#[derive(Debug, Clone)]
+struct Active {
+    cell: usize,
+    neighbours: Range<usize>,
+}
+
+/// A grid, and the neighbourhood and rule that move it on a step at a time.
+///
+/// Cells the rule never changes can only affect which cells are neighbours,
+/// so for `Adjacent` and `LineOfSight` the neighbours of every other cell
+/// are worked out once, up front, leaving out the fixed cells. Stepping then
+/// only looks at the cells that can change, and never walks across the fixed
+/// ones again. A `Custom` neighbourhood might depend on anything on the grid,
+/// so its neighbours are worked out again after every step.
+pub struct Automaton<S> {
+    grid: Grid<S>,
+    /// Where the next step is written, before it's swapped with the grid's
+    /// cells, so stepping doesn't allocate a new grid every time.
+    next: Vec<S>,
+    /// In the order of the cells.
+    active: Vec<Active>,
+    /// The neighbours of all the active cells, one after the other.
+    neighbours: Vec<usize>,
+    neighbourhood: Neighbourhood<S>,
+    rule: Rule<S>,
+}
+
+impl<S: Copy + PartialEq> Automaton<S> {
+    /// Fails if a `LineOfSight` neighbourhood sees through live or dead
+    /// cells, since what's in sight would then change from step to step.
+    pub fn new(
+        grid: Grid<S>,
+        neighbourhood: Neighbourhood<S>,
+        rule: Rule<S>,
+    ) -> Result<Automaton<S>, String> {
+        if let Neighbourhood::LineOfSight(see_through) = neighbourhood {
+            if see_through(rule.live) || see_through(rule.dead) {
+                return Err(String::from(
+                    "Line of sight can only see through cells the rule never changes",
+                ));
+            }
+        }
+        let mut automaton = Automaton {
+            next: Vec::with_capacity(grid.cells.len()),
+            grid,
+            active: vec![],
+            neighbours: vec![],
+            neighbourhood,
+            rule,
+        };
+        automaton.find_neighbours();
+        Ok(automaton)
+    }
+
+    /// List the cells the rule can change, and their neighbours that it can
+    /// change too, reusing the lists' space.
+    fn find_neighbours(&mut self) {
+        let Automaton {
+            grid,
+            active,
+            neighbours,
+            neighbourhood,
+            rule,
+            ..
+        } = self;
+        active.clear();
+        neighbours.clear();
+        for y in 0..grid.rows {
+            for x in 0..grid.cols {
+                if !rule.applies_to(grid.get(x, y)) {
+                    continue;
+                }
+                let start = neighbours.len();
+                neighbours.extend(
+                    neighbourhood
+                        .neighbours(grid, x, y)
+                        .into_iter()
+                        .filter(|&(nx, ny)| rule.applies_to(grid.get(nx, ny)))
+                        .map(|(nx, ny)| ny * grid.cols + nx),
+                );
+                active.push(Active {
+                    cell: y * grid.cols + x,
+                    neighbours: start..neighbours.len(),
+                });
+            }
+        }
+    }
+
+    pub fn grid(&self) -> &Grid<S> {
+        &self.grid
+    }
+
+    /// How many of the neighbours of the cell at `(x, y)` are live. Cells
+    /// the rule never changes don't have any.
+    pub fn live_neighbours(&self, x: usize, y: usize) -> usize {
+        let cell = y * self.grid.cols + x;
+        match self
+            .active
+            .binary_search_by_key(&cell, |active| active.cell)
+        {
+            Ok(index) => self.count_live(&self.active[index]),
+            Err(_) => 0,
+        }
+    }
+
+    fn count_live(&self, active: &Active) -> usize {
+        self.neighbours[active.neighbours.clone()]
+            .iter()
+            .filter(|&&neighbour| self.grid.cells[neighbour] == self.rule.live)
+            .count()
+    }
+
+    /// Move every cell on to its next state at once, returning how many
+    /// cells changed.
+    pub fn step(&mut self) -> usize {
+        let mut next = mem::take(&mut self.next);
+        next.clear();
+        next.extend_from_slice(&self.grid.cells);
+        let mut changed = 0;
+        for active in &self.active {
+            let state = self.grid.cells[active.cell];
+            let new_state = self.rule.next(state, self.count_live(active));
+            if new_state != state {
+                next[active.cell] = new_state;
+                changed += 1;
+            }
+        }
+        self.next = mem::replace(&mut self.grid.cells, next);
+        if let Neighbourhood::Custom(_) = self.neighbourhood {
+            self.find_neighbours();
+        }
+        changed
+    }
+
+    /// Step until a step changes nothing, returning how many steps did change
+    /// something and the grid they ended with.
+    ///
+    /// A grid has only so many states, so a rule that never settles ends up
+    /// going round in a cycle instead. That's noticed by keeping a copy of
+    /// the grid and checking every step whether it's back to the copy, with
+    /// the copy renewed after 1, 2, 4, 8... steps (Brent's algorithm). Once
+    /// the copy is in the cycle and the wait is at least the period, the
+    /// first repeat gives the period.
+    pub fn run_until_stable(&mut self) -> Result<(usize, &Grid<S>), Cycle> {
+        let mut saved = self.grid.cells.clone();
+        let mut since_saved = 0;
+        let mut wait = 1;
+        let mut steps = 0;
+        loop {
+            if self.step() == 0 {
+                return Ok((steps, &self.grid));
+            }
+            steps += 1;
+            since_saved += 1;
+            if self.grid.cells == saved {
+                return Err(Cycle {
+                    steps,
+                    period: since_saved,
+                });
+            }
+            if since_saved == wait {
+                saved.clone_from(&self.grid.cells);
+                since_saved = 0;
+                wait *= 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn life(rows: &[&str]) -> Grid<bool> {
+        Grid::new(
+            rows.iter()
+                .map(|row| row.chars().map(|c| c == '#').collect())
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    fn conway() -> Rule<bool> {
+        Rule {
+            live: true,
+            dead: false,
+            birth: 3..=3,
+            survival: 2..=3,
+        }
+    }
+
+    /// The adjacent cells, wrapping around the edges.
+    fn torus() -> Neighbourhood<bool> {
+        Neighbourhood::Custom(Box::new(|grid: &Grid<bool>, x, y| {
+            DIRECTIONS
+                .iter()
+                .map(|&(dx, dy)| {
+                    let x = (x as isize + dx).rem_euclid(grid.cols() as isize) as usize;
+                    let y = (y as isize + dy).rem_euclid(grid.rows() as isize) as usize;
+                    (x, y)
+                })
+                .collect()
+        }))
+    }
+
+    #[test]
+    fn test_blinker() {
+        let vertical = life(&[".....", "..#..", "..#..", "..#..", "....."]);
+        let horizontal = life(&[".....", ".....", ".###.", ".....", "....."]);
+        let mut automaton =
+            Automaton::new(vertical.clone(), Neighbourhood::Adjacent, conway()).unwrap();
+
+        assert_eq!(automaton.step(), 4);
+        assert_eq!(automaton.grid(), &horizontal);
+        assert_eq!(automaton.step(), 4);
+        assert_eq!(automaton.grid(), &vertical);
+        assert_eq!(automaton.grid().count(true), 3);
+
+        assert_eq!(
+            automaton.run_until_stable().unwrap_err(),
+            Cycle {
+                steps: 3,
+                period: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_run_until_stable() {
+        // A glider flies into the corner and turns into a block
+        let glider = life(&[".#....", "..#...", "###...", "......", "......", "......"]);
+        let block = life(&["......", "......", "......", "......", "....##", "....##"]);
+        let mut automaton = Automaton::new(glider, Neighbourhood::Adjacent, conway()).unwrap();
+
+        let (steps, grid) = automaton.run_until_stable().unwrap();
+        assert_eq!(grid, &block);
+        assert_eq!(steps, 15);
+        assert_eq!(automaton.run_until_stable(), Ok((0, &block)));
+    }
+
+    #[test]
+    fn test_long_cycle() {
+        // A glider on a torus never settles, and comes back to the same place
+        // after flying all the way around
+        let glider = life(&[".#....", "..#...", "###...", "......", "......", "......"]);
+        let mut automaton = Automaton::new(glider, torus(), conway()).unwrap();
+
+        assert_eq!(automaton.run_until_stable().unwrap_err().period, 24);
+    }
+
+    #[test]
+    fn test_neighbourhoods() {
+        let grid = life(&["#...", "....", "#.#.", "...#"]);
+
+        let mut adjacent = Neighbourhood::Adjacent.neighbours(&grid, 0, 0);
+        adjacent.sort();
+        assert_eq!(adjacent, vec![(0, 1), (1, 0), (1, 1)]);
+
+        // Looking through dead cells, from the corner
+        let sight = Neighbourhood::LineOfSight(|live: bool| !live);
+        let mut seen = sight.neighbours(&grid, 0, 0);
+        seen.sort();
+        assert_eq!(seen, vec![(0, 2), (2, 2)]);
+
+        // Wrapping around the edges
+        let automaton = Automaton::new(grid, torus(), conway()).unwrap();
+        assert_eq!(automaton.live_neighbours(0, 0), 1);
+        assert_eq!(automaton.live_neighbours(3, 0), 2);
+    }
+
+    #[test]
+    fn test_fixed_cells() {
+        // Walls (None) never change, and are seen across
+        let grid = Grid::new(vec![
+            vec![Some(true), None, None, Some(false)],
+            vec![None, None, None, None],
+            vec![Some(true), None, None, Some(true)],
+        ])
+        .unwrap();
+        let mut automaton = Automaton::new(
+            grid,
+            Neighbourhood::LineOfSight(|cell: Option<bool>| cell.is_none()),
+            Rule {
+                live: Some(true),
+                dead: Some(false),
+                birth: 2..=2,
+                survival: 0..=0,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(automaton.live_neighbours(3, 0), 2);
+        assert_eq!(automaton.live_neighbours(0, 0), 1);
+        assert_eq!(automaton.live_neighbours(1, 1), 0);
+
+        assert_eq!(automaton.step(), 4);
+        assert_eq!(automaton.grid().count(None), 8);
+        assert_eq!(automaton.grid().count(Some(true)), 1);
+    }
+
+    #[test]
+    fn test_line_of_sight_through_dead_cells() {
+        assert!(Automaton::new(
+            life(&["#.#"]),
+            Neighbourhood::LineOfSight(|live: bool| !live),
+            conway(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_custom_neighbourhood_follows_the_grid() {
+        // Each cell's neighbours are the other live cells in its row, so
+        // they change as cells come to life
+        let row = Neighbourhood::Custom(Box::new(|grid: &Grid<bool>, x, y| {
+            (0..grid.cols())
+                .filter(|&other| other != x && grid.get(other, y))
+                .map(|other| (other, y))
+                .collect()
+        }));
+        let rule = Rule {
+            live: true,
+            dead: false,
+            birth: 1..=1,
+            survival: 0..=8,
+        };
+        let mut automaton = Automaton::new(life(&["#..."]), row, rule).unwrap();
+
+        assert_eq!(automaton.live_neighbours(1, 0), 1);
+        assert_eq!(automaton.step(), 3);
+        assert_eq!(automaton.live_neighbours(1, 0), 3);
+    }
+
+    #[test]
+    fn test_ragged_grid() {
+        assert_eq!(
+            Grid::new(vec![vec![1, 2], vec![3]]),
+            Err(String::from("Row 1 has 1 cells, but row 0 has 2"))
+        );
+    }
+}
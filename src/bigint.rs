@@ -0,0 +1,239 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+/// A minimal arbitrary-precision unsigned integer, for answers that outgrow
+/// the primitive types.
+///
+/// Only the operations the puzzles need are implemented: addition,
+/// multiplication, subtraction, comparison and decimal parsing and
+/// formatting.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BigUint {
+    /// Base 2^32 digits, least significant first, without trailing zeroes.
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> BigUint {
+        BigUint { limbs: vec![] }
+    }
+
+    pub fn one() -> BigUint {
+        BigUint::from(1u64)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// The value as a `u128`, if it fits.
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.limbs.len() > 4 {
+            return None;
+        }
+        Some(
+            self.limbs
+                .iter()
+                .rev()
+                .fold(0u128, |acc, &limb| (acc << 32) | limb as u128),
+        )
+    }
+
+    /// `self - other`, or `None` if that would be negative.
+    pub fn checked_sub(&self, other: &BigUint) -> Option<BigUint> {
+        if other.limbs.len() > self.limbs.len() {
+            return None;
+        }
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for (i, &limb) in self.limbs.iter().enumerate() {
+            let mut difference = limb as i64 - *other.limbs.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = 0;
+            if difference < 0 {
+                difference += 1 << 32;
+                borrow = 1;
+            }
+            limbs.push(difference as u32);
+        }
+        if borrow != 0 {
+            return None;
+        }
+        Some(BigUint { limbs }.trim())
+    }
+
+    /// Base 2^32 digits, least significant first, without trailing zeroes.
+    pub fn limbs(&self) -> &[u32] {
+        &self.limbs
+    }
+
+    pub fn from_limbs(limbs: Vec<u32>) -> BigUint {
+        BigUint { limbs }.trim()
+    }
+
+    fn trim(mut self) -> BigUint {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        self
+    }
+
+    /// Divide in place by a small divisor, returning the remainder.
+    fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let current = (remainder << 32) | *limb as u64;
+            *limb = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        remainder as u32
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(n: u64) -> Self {
+        BigUint {
+            limbs: vec![n as u32, (n >> 32) as u32],
+        }
+        .trim()
+    }
+}
+
+impl From<usize> for BigUint {
+    fn from(n: usize) -> Self {
+        BigUint::from(n as u64)
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &BigUint) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &BigUint) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for BigUint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid number {:?}", s));
+        }
+        let ten = BigUint::from(10u64);
+        Ok(s.bytes().fold(BigUint::zero(), |n, digit| {
+            &(&n * &ten) + &BigUint::from((digit - b'0') as u64)
+        }))
+    }
+}
+
+impl Add for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0u64;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            let sum = carry
+                + *self.limbs.get(i).unwrap_or(&0) as u64
+                + *other.limbs.get(i).unwrap_or(&0) as u64;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        BigUint { limbs }.trim()
+    }
+}
+
+impl Mul for &BigUint {
+    type Output = BigUint;
+
+    fn mul(self, other: &BigUint) -> BigUint {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let product = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+                limbs[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        BigUint { limbs }.trim()
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Peel off nine decimal digits at a time
+        let mut n = self.clone();
+        let mut chunks = vec![];
+        while !n.is_zero() {
+            chunks.push(n.div_rem_small(1_000_000_000));
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_values() {
+        assert_eq!(BigUint::zero().to_string(), "0");
+        assert_eq!(BigUint::one().to_string(), "1");
+        assert_eq!(BigUint::from(u64::MAX).to_u128(), Some(u64::MAX as u128));
+        assert_eq!(
+            (&BigUint::from(u64::MAX) + &BigUint::one()).to_u128(),
+            Some(u64::MAX as u128 + 1)
+        );
+    }
+
+    #[test]
+    fn test_powers_of_two() {
+        let two = BigUint::from(2u64);
+        let mut n = BigUint::one();
+        for _ in 0..128 {
+            n = &n * &two;
+        }
+        assert_eq!(n.to_string(), "340282366920938463463374607431768211456");
+        assert_eq!(n.to_u128(), None);
+        assert_eq!((&n * &BigUint::zero()), BigUint::zero());
+        assert_eq!(
+            n.checked_sub(&BigUint::one()).unwrap().to_u128(),
+            Some(u128::MAX)
+        );
+        assert_eq!(BigUint::one().checked_sub(&n), None);
+    }
+
+    #[test]
+    fn test_parse_and_compare() {
+        let big: BigUint = "340282366920938463463374607431768211456".parse().unwrap();
+
+        assert_eq!(big.to_u128(), None);
+        assert_eq!(big.to_string(), "340282366920938463463374607431768211456");
+        assert!(big > BigUint::from(u64::MAX));
+        assert!(BigUint::from(5u64) < BigUint::from(7u64));
+        assert!("12a".parse::<BigUint>().is_err());
+        assert_eq!(BigUint::from_limbs(vec![7, 0, 0]), BigUint::from(7u64));
+    }
+}
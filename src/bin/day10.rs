@@ -1,110 +1,532 @@
-use aoc2020::read_string_input;
+use aoc2020::bigint::BigUint;
+use aoc2020::rng::Rng;
+use aoc2020::{option_value, read_string_input};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::process;
 
-fn main() {
-    let input = read_string_input("in-data/day10.txt");
+/// How adapters connect: each takes an input between `min_gap` and
+/// `max_gap` jolts below its rating, and the device is rated `device_offset`
+/// above the highest adapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Joltage {
+    min_gap: usize,
+    max_gap: usize,
+    device_offset: usize,
+}
+
+/// The rules from the puzzle: gaps of 1 to 3, and a device 3 above the
+/// highest adapter.
+impl Default for Joltage {
+    fn default() -> Self {
+        Joltage {
+            min_gap: 1,
+            max_gap: 3,
+            device_offset: 3,
+        }
+    }
+}
+
+impl Joltage {
+    fn allows(&self, gap: usize) -> bool {
+        gap >= self.min_gap && gap <= self.max_gap
+    }
 
-    println!("Part 1: {}", part_1(&input));
-    println!("Part 2: {}", part_2(&input));
+    /// Whether any gap is allowed at all.
+    fn check(&self) -> Result<(), String> {
+        if self.min_gap > self.max_gap {
+            return Err(format!(
+                "The minimum gap ({}) can't be more than the maximum gap ({})",
+                self.min_gap, self.max_gap
+            ));
+        }
+        Ok(())
+    }
 }
 
-fn part_1(input: &str) -> usize {
-    let mut data: Vec<usize> = input
+/// Why the adapters can't all be chained together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ChainError {
+    /// Nothing can go between `from` and `to` jolts, where `to` is the next
+    /// adapter or the device.
+    Gap { from: usize, to: usize },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::Gap { from, to } => write!(
+                f,
+                "Can't chain the adapters: nothing connects {} jolts to {} jolts",
+                from, to
+            ),
+        }
+    }
+}
+
+/// The adapters' ratings, in order.
+fn parse_adapters(input: &str) -> Vec<usize> {
+    let mut adapters: Vec<usize> = input
         .split_ascii_whitespace()
         .map(|s| s.parse::<usize>().unwrap())
         .collect();
+    adapters.sort_unstable();
+    adapters
+}
+
+const USAGE: &str = "Usage: day10 [--input <file>] [--min-gap <n>] [--max-gap <n>] \
+[--device-offset <n>] [--seed <n>] [histogram | clusters | chains [n] | nth <k> | sample | \
+shortest]";
+
+fn print_chain(chain: &[usize]) {
+    let jolts: Vec<String> = chain.iter().map(usize::to_string).collect();
+    println!("{}", jolts.join(" "));
+}
+
+fn main() {
+    // Options:
+    //   --input <file>         another list of adapters
+    //   --min-gap <n>          the smallest step up an adapter takes (default 1)
+    //   --max-gap <n>          the biggest step up an adapter takes (default 3)
+    //   --device-offset <n>    how far above the highest adapter the device
+    //                          is (default 3)
+    //   --seed <n>             for sample (default 0)
+    // Commands:
+    //   histogram              how many steps of each size the full chain has
+    //   clusters               the arrangements broken down into clusters
+    //                          that multiply together
+    //   chains [n]             the first n arrangements (default 10), in
+    //                          lexicographic order
+    //   nth <k>                the k-th arrangement, counting from 0
+    //   sample                 an arrangement picked uniformly at random
+    //   shortest               an arrangement with as few adapters as possible
+    let mut filename = String::from("in-data/day10.txt");
+    let mut joltage = Joltage::default();
+    let mut seed = 0;
+    let mut args: Vec<String> = vec![];
+    let mut options = env::args().skip(1);
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--input" => filename = option_value(options.next(), USAGE),
+            "--min-gap" => joltage.min_gap = option_value(options.next(), USAGE),
+            "--max-gap" => joltage.max_gap = option_value(options.next(), USAGE),
+            "--device-offset" => joltage.device_offset = option_value(options.next(), USAGE),
+            "--seed" => seed = option_value(options.next(), USAGE),
+            _ => args.push(arg),
+        }
+    }
+    if let Err(error) = joltage.check() {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+    let input = read_string_input(&filename);
+    let adapters = parse_adapters(&input);
+    let arrangements = Arrangements::new(&adapters, &joltage);
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<&str>>()
+        .as_slice()
+    {
+        [] => {
+            match part_1(&input, &joltage) {
+                Ok(answer) => println!("Part 1: {}", answer),
+                Err(error) => println!("Part 1: {}", error),
+            }
+            println!("Part 2: {}", part_2(&input, &joltage));
+        }
+        ["histogram"] => match differences(&adapters, &joltage) {
+            Ok(counts) => {
+                for (gap, count) in counts {
+                    println!("{}: {}", gap, count);
+                }
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        ["chains"] => arrangements.chains().take(10).for_each(|c| print_chain(&c)),
+        ["chains", n] => arrangements
+            .chains()
+            .take(option_value(Some(n.to_string()), USAGE))
+            .for_each(|c| print_chain(&c)),
+        ["nth", k] => match arrangements.nth(&option_value(Some(k.to_string()), USAGE)) {
+            Some(chain) => print_chain(&chain),
+            None => println!("There are only {} arrangements", arrangements.count()),
+        },
+        ["sample"] => match arrangements.sample(&mut Rng::new(seed)) {
+            Some(chain) => print_chain(&chain),
+            None => println!("There are no arrangements"),
+        },
+        ["clusters"] => println!("{}", breakdown(&clusters(&adapters, &joltage), &joltage)),
+        ["shortest"] => match arrangements.shortest() {
+            Some(chain) => print_chain(&chain),
+            None => println!("There are no arrangements"),
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    }
+}
+
+/// How many steps of each size there are when every adapter is used, from
+/// the outlet at 0 to the device, for each gap that occurs.
+fn differences(
+    adapters: &[usize],
+    joltage: &Joltage,
+) -> Result<BTreeMap<usize, usize>, ChainError> {
+    let mut counts = BTreeMap::new();
+    let device = adapters.last().unwrap_or(&0) + joltage.device_offset;
+    let mut from = 0;
+    for &to in adapters.iter().chain(Some(&device)) {
+        let gap = to - from;
+        if !joltage.allows(gap) {
+            return Err(ChainError::Gap { from, to });
+        }
+        *counts.entry(gap).or_insert(0) += 1;
+        from = to;
+    }
+    Ok(counts)
+}
+
+/// The number of 1-jolt steps times the number of 3-jolt steps.
+fn part_1(input: &str, joltage: &Joltage) -> Result<usize, ChainError> {
+    let counts = differences(&parse_adapters(input), joltage)?;
+    let count = |gap: usize| counts.get(&gap).copied().unwrap_or(0);
+
+    Ok(count(1) * count(3))
+}
+
+/// The number of ways to get from the outlet to the device, for any input.
+///
+/// Going through the adapters in order, the ways to reach each one is the sum
+/// of the ways to reach the adapters it can connect to. The counts grow
+/// exponentially with the number of adapters, so they're `BigUint`s.
+fn part_2(input: &str, joltage: &Joltage) -> BigUint {
+    count_arrangements(&parse_adapters(input), joltage)
+}
+
+/// Arrangements of sorted `adapters`, starting from the outlet at 0.
+fn count_arrangements(adapters: &[usize], joltage: &Joltage) -> BigUint {
+    Arrangements::new(adapters, joltage).count().clone()
+}
+
+/// Every way of chaining the adapters from the outlet to the device.
+///
+/// The outlet, adapters and device form a graph, where each connects to the
+/// ones above it within the allowed gaps. Counting the ways to finish from
+/// each of them, working back from the device, is enough to enumerate, index
+/// and sample arrangements without building them all.
+///
+/// An arrangement is the list of adapters it uses. They're ordered
+/// lexicographically, so an arrangement comes before any that extend it.
+#[derive(Debug, Clone)]
+struct Arrangements {
+    /// The outlet, the adapters and the device, in order.
+    jolts: Vec<usize>,
+    joltage: Joltage,
+    /// The number of ways to get from each of `jolts` to the device.
+    ways_from: Vec<BigUint>,
+}
+
+impl Arrangements {
+    fn new(adapters: &[usize], joltage: &Joltage) -> Arrangements {
+        let mut jolts = vec![0];
+        jolts.extend_from_slice(adapters);
+        jolts.push(adapters.last().unwrap_or(&0) + joltage.device_offset);
+
+        let mut arrangements = Arrangements {
+            ways_from: vec![BigUint::zero(); jolts.len()],
+            jolts,
+            joltage: *joltage,
+        };
+        let device = arrangements.device();
+        arrangements.ways_from[device] = BigUint::one();
+        for i in (0..device).rev() {
+            let mut ways = BigUint::zero();
+            for j in arrangements.next(i) {
+                ways = &ways + &arrangements.ways_from[j];
+            }
+            arrangements.ways_from[i] = ways;
+        }
+        arrangements
+    }
+
+    fn device(&self) -> usize {
+        self.jolts.len() - 1
+    }
+
+    /// What `i` connects to, in the order arrangements go in: the device
+    /// first, then the adapters from the lowest.
+    fn next(&self, i: usize) -> Vec<usize> {
+        let mut next: Vec<usize> = (i + 1..self.device())
+            .take_while(|&j| self.jolts[j] - self.jolts[i] <= self.joltage.max_gap)
+            .filter(|&j| self.joltage.allows(self.jolts[j] - self.jolts[i]))
+            .collect();
+        if i < self.device()
+            && self
+                .joltage
+                .allows(self.jolts[self.device()] - self.jolts[i])
+        {
+            next.insert(0, self.device());
+        }
+        next
+    }
+
+    fn count(&self) -> &BigUint {
+        &self.ways_from[0]
+    }
+
+    /// Turn a path through the graph into the adapters it uses.
+    fn chain(&self, path: &[usize]) -> Vec<usize> {
+        path.iter()
+            .filter(|&&i| i != 0 && i != self.device())
+            .map(|&i| self.jolts[i])
+            .collect()
+    }
+
+    /// All the arrangements, in order, built as they're needed.
+    fn chains(&self) -> Chains<'_> {
+        Chains {
+            arrangements: self,
+            path: vec![],
+            stack: vec![vec![0]],
+        }
+    }
+
+    /// The `k`-th arrangement in order, counting from 0, found by skipping
+    /// whole subtrees of arrangements at a time.
+    fn nth(&self, k: &BigUint) -> Option<Vec<usize>> {
+        if k >= self.count() {
+            return None;
+        }
+        let mut k = k.clone();
+        let mut path = vec![0];
+        let mut i = 0;
+        while i != self.device() {
+            for j in self.next(i) {
+                match k.checked_sub(&self.ways_from[j]) {
+                    Some(rest) => k = rest,
+                    None => {
+                        i = j;
+                        break;
+                    }
+                }
+            }
+            path.push(i);
+        }
+        Some(self.chain(&path))
+    }
+
+    /// An arrangement picked uniformly at random.
+    fn sample(&self, rng: &mut Rng) -> Option<Vec<usize>> {
+        if self.count().is_zero() {
+            return None;
+        }
+        self.nth(&random_below(rng, self.count()))
+    }
+
+    /// An arrangement using as few adapters as possible, the first in order
+    /// if there are several.
+    fn shortest(&self) -> Option<Vec<usize>> {
+        // The fewest steps from each node to the device, backwards like
+        // ways_from
+        let device = self.device();
+        let mut steps: Vec<Option<usize>> = vec![None; self.jolts.len()];
+        steps[device] = Some(0);
+        for i in (0..device).rev() {
+            steps[i] = self
+                .next(i)
+                .iter()
+                .filter_map(|&j| steps[j])
+                .min()
+                .map(|s| s + 1);
+        }
+
+        steps[0]?;
+        let mut path = vec![0];
+        let mut i = 0;
+        while i != device {
+            i = self
+                .next(i)
+                .into_iter()
+                .find(|&j| steps[j].map(|s| s + 1) == steps[i])
+                .unwrap();
+            path.push(i);
+        }
+        Some(self.chain(&path))
+    }
+}
+
+/// A number in `0..n`, each equally likely.
+fn random_below(rng: &mut Rng, n: &BigUint) -> BigUint {
+    let top = *n.limbs().last().unwrap();
+    // Only as many bits in the top limb as n has, so at least half of the
+    // candidates are below n
+    let mask = u32::MAX >> top.leading_zeros();
+    loop {
+        let mut limbs: Vec<u32> = n.limbs().iter().map(|_| rng.next_u64() as u32).collect();
+        *limbs.last_mut().unwrap() &= mask;
+        let candidate = BigUint::from_limbs(limbs);
+        if &candidate < n {
+            return candidate;
+        }
+    }
+}
 
-    data.sort_unstable();
-
-    // This has length 4 due to zero-indexing
-    // The zeroeth and and second index is never used, but I can use this vec as
-    // a cheap map by doing differences[some_index]
-    let mut differences: Vec<usize> = vec![0, 0, 0, 0];
-
-    let mut last_jolts_spec = 0;
-
-    for number in data {
-        let diff = number - last_jolts_spec;
-        differences[diff] += 1;
-        last_jolts_spec = number;
-    }
-
-    differences[3] += 1;
-
-    differences[1] * differences[3]
-}
-
-fn part_2(_input: &str) -> usize {
-    // The solution to this is to find how many paths there are through each cluster where skips might appear.
-    //
-    // I got the idea for this by looking up the prime factors of the answer to the longer sample.
-    //
-    // The prime factors of 19208 are: 2 * 2 * 2 * 7 * 7 * 7 * 7
-    //
-    // If we look at the clusters of that example, we find 4 clusters with 5
-    // consecutive numbers (each with 7 possible paths through them), 1 cluster
-    // with 4 numbers (4 possible paths = 2 * 2), and one cluster with 3
-    // consecutive numbers (2 possible paths).
-    //
-    // These numbers correspond with the prime factors of 19208...
-    //
-    // I solved this manually, by pasting the input into an Emacs buffer,
-    // sorting it, grouping clusters where skips might happen, and then manually
-    // calculating how many possible paths there are through each cluster. The
-    // paths are shown after the arrows in the following section:
-    //
-    // 0 1 2 -> 2
-    // 5 6 7 8 9 -> 7
-    // 12 -> 1
-    // 13 -> 1
-    // 16 17 18 19 -> 4
-    // 22 -> 1
-    // 25 26 27 28 -> 4
-    // 31 -> 1
-    // 34 35 36 37 38 -> 7
-    // 41 -> 1
-    // 44 45 46 47 48 -> 7
-    // 51 52 53 -> 2
-    // 56 -> 1
-    // 59 60 61 62 -> 4
-    // 65 66 67 68 -> 4
-    // 71 72 73 74 75 -> 7
-    // 78 -> 1
-    // 81 -> 1
-    // 84 -> 1
-    // 87 88 89 90 -> 4
-    // 93 94 95 -> 2
-    // 98 -> 1
-    // 99 -> 1
-    // 102 103 104 105 106 -> 7
-    // 109 110 111 112 113 -> 7
-    // 116 117 118 -> 2
-    // 121 122 123 124 -> 4
-    // 127 -> 1
-    // 130 -> 1
-    // 133 134 135 136 137 -> 7
-    // 140 141 142 -> 2
-    // 145 146 147 148 149 -> 7
-    // 152 -> 1
-    // 153 -> 1
-    // 156 -> 1
-    // 159 160 161 -> 2
-    // 164 -> 1
-    // 165 -> 1
-    // 168 169 170 171 172 -> 7
-    // 175 -> 1
-    // 178 179 180 181 182 -> 7
-    //
-    // The solution is the product of these paths, so if we skip the ones here, that would yield:
-    2 * 7 * 4 * 4 * 7 * 7 * 2 * 4 * 4 * 7 * 4 * 2 * 7 * 7 * 2 * 4 * 7 * 2 * 7 * 2 * 7 * 7
+/// A depth-first walk through the arrangements, only ever going down paths
+/// that reach the device.
+struct Chains<'a> {
+    arrangements: &'a Arrangements,
+    /// The nodes on the current path.
+    path: Vec<usize>,
+    /// For each node on the path, and the one before the start, the nodes
+    /// still to try after it, last first.
+    stack: Vec<Vec<usize>>,
+}
+
+impl Iterator for Chains<'_> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let arrangements = self.arrangements;
+        while let Some(options) = self.stack.last_mut() {
+            match options.pop() {
+                Some(i) if arrangements.ways_from[i].is_zero() => {}
+                Some(i) if i == arrangements.device() => {
+                    return Some(arrangements.chain(&self.path));
+                }
+                Some(i) => {
+                    self.path.push(i);
+                    let mut next = arrangements.next(i);
+                    next.reverse();
+                    self.stack.push(next);
+                }
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+// Part 2 was first solved by hand, by finding how many paths there are
+// through each cluster where skips might appear.
+//
+// I got the idea for this by looking up the prime factors of the answer to the longer sample.
+//
+// The prime factors of 19208 are: 2 * 2 * 2 * 7 * 7 * 7 * 7
+//
+// If we look at the clusters of that example, we find 4 clusters with 5
+// consecutive numbers (each with 7 possible paths through them), 1 cluster
+// with 4 numbers (4 possible paths = 2 * 2), and one cluster with 3
+// consecutive numbers (2 possible paths).
+//
+// These numbers correspond with the prime factors of 19208...
+//
+// That was done by sorting the input in an Emacs buffer and grouping it by
+// hand; `clusters` does the same for any input, and `day10 clusters` prints
+// the table of clusters and their paths.
+
+/// A run of joltages that no connection skips over as a whole, so every
+/// arrangement goes through its first and last, and the number of
+/// arrangements is the product of the paths through each cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cluster {
+    jolts: Vec<usize>,
+    /// The ways to get from the first joltage to the last. For a run of
+    /// consecutive joltages with the puzzle's rules, these are the
+    /// tribonacci numbers: 1, 1, 2, 4, 7, 13...
+    paths: BigUint,
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let jolts: Vec<String> = self.jolts.iter().map(usize::to_string).collect();
+        write!(f, "{} -> {}", jolts.join(" "), self.paths)
+    }
+}
+
+/// Split the outlet, adapters and device into clusters.
+///
+/// There's a boundary between two neighbours when the only connection
+/// across it is the one between them: the joltages either side of the pair
+/// are both too far from the other one.
+fn clusters(adapters: &[usize], joltage: &Joltage) -> Vec<Cluster> {
+    let mut jolts = vec![0];
+    jolts.extend_from_slice(adapters);
+    jolts.push(adapters.last().unwrap_or(&0) + joltage.device_offset);
+    let reaches = |i: usize, j: usize| jolts[j] - jolts[i] <= joltage.max_gap;
+
+    let mut clusters = vec![];
+    let mut start = 0;
+    for i in 0..jolts.len() {
+        let last = i + 1 == jolts.len();
+        let boundary = last
+            || ((i == 0 || !reaches(i - 1, i + 1)) && (i + 2 >= jolts.len() || !reaches(i, i + 2)));
+        if boundary {
+            let run = &jolts[start..=i];
+            let mut ways = vec![BigUint::one()];
+            for (k, &to) in run.iter().enumerate().skip(1) {
+                let mut count = BigUint::zero();
+                for (from, way) in run[..k].iter().zip(&ways) {
+                    if joltage.allows(to - from) {
+                        count = &count + way;
+                    }
+                }
+                ways.push(count);
+            }
+            clusters.push(Cluster {
+                jolts: run.to_vec(),
+                paths: ways.pop().unwrap(),
+            });
+            start = i + 1;
+        }
+    }
+    clusters
+}
+
+/// The table from the manual analysis: each cluster and its paths, then the
+/// product of the paths that aren't 1. With a gap between clusters that
+/// isn't allowed, the answer is 0 however the clusters work out.
+fn breakdown(clusters: &[Cluster], joltage: &Joltage) -> String {
+    let mut lines: Vec<String> = clusters.iter().map(Cluster::to_string).collect();
+
+    let factors: Vec<&BigUint> = clusters
+        .iter()
+        .map(|cluster| &cluster.paths)
+        .filter(|&paths| *paths != BigUint::one())
+        .collect();
+    let connected = clusters
+        .windows(2)
+        .all(|pair| joltage.allows(pair[1].jolts[0] - pair[0].jolts.last().unwrap()));
+    let product = if connected {
+        factors
+            .iter()
+            .fold(BigUint::one(), |product, &paths| &product * paths)
+    } else {
+        BigUint::zero()
+    };
+    let factors: Vec<String> = factors.iter().map(|paths| paths.to_string()).collect();
+
+    lines.push(String::new());
+    lines.push(format!("{} = {}", factors.join(" * "), product));
+    lines.join("\n")
 }
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
 
     #[test]
     fn test_part_2_simple_sample() {
-        let _sample_input = "16
+        let sample_input = "16
 10
 15
 5
@@ -115,12 +537,15 @@ mod tests {
 6
 12
 4";
-        // assert_eq!(part_2(&sample_input), 8);
+        assert_eq!(
+            part_2(sample_input, &Joltage::default()),
+            BigUint::from(8u64)
+        );
     }
 
     #[test]
     fn test_part_2_more_complex_sample() {
-        let _sample_input = "28
+        let sample_input = "28
 33
 18
 42
@@ -151,6 +576,227 @@ mod tests {
 34
 10
 3";
-        // assert_eq!(part_2(&sample_input), 19208);
+        assert_eq!(
+            part_2(sample_input, &Joltage::default()),
+            BigUint::from(19208u64)
+        );
+        assert_eq!(part_1(sample_input, &Joltage::default()), Ok(22 * 10));
+    }
+
+    #[test]
+    fn test_huge_input() {
+        // With every joltage from 1 to n, the counts are the tribonacci
+        // numbers
+        let adapters: Vec<usize> = (1..=10).collect();
+        assert_eq!(
+            count_arrangements(&adapters, &Joltage::default()),
+            BigUint::from(274u64)
+        );
+
+        // Far more arrangements than fit in a u128
+        let adapters: Vec<usize> = (1..=300).collect();
+        assert_eq!(
+            count_arrangements(&adapters, &Joltage::default()).to_u128(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tolerance_and_offset() {
+        let adapters = parse_adapters("1 2 4 8 10");
+        let wide = Joltage {
+            min_gap: 1,
+            max_gap: 4,
+            device_offset: 2,
+        };
+
+        assert_eq!(
+            differences(&adapters, &wide),
+            Ok(vec![(1, 2), (2, 3), (4, 1)].into_iter().collect())
+        );
+        assert_eq!(part_1("1 2 4 8 10", &wide), Ok(0));
+        // 4 ways from 0 to 4 (via 1 and 2, 1, 2 or neither), and 10 is
+        // optional
+        assert_eq!(count_arrangements(&adapters, &wide), BigUint::from(8u64));
+
+        assert_eq!(
+            differences(&adapters, &Joltage::default()),
+            Err(ChainError::Gap { from: 4, to: 8 })
+        );
+        assert_eq!(
+            count_arrangements(&adapters, &Joltage::default()),
+            BigUint::zero()
+        );
+        assert_eq!(
+            part_1(
+                "1 2 3",
+                &Joltage {
+                    min_gap: 1,
+                    max_gap: 3,
+                    device_offset: 5
+                }
+            )
+            .unwrap_err()
+            .to_string(),
+            "Can't chain the adapters: nothing connects 3 jolts to 8 jolts"
+        );
+
+        // Steps of exactly 2, so 3 can be left out but nothing reaches 5
+        let even = Joltage {
+            min_gap: 2,
+            max_gap: 2,
+            device_offset: 2,
+        };
+        assert_eq!(
+            differences(&parse_adapters("2 4"), &even),
+            Ok(vec![(2, 3)].into_iter().collect())
+        );
+        assert_eq!(
+            count_arrangements(&parse_adapters("2 3 5"), &even),
+            BigUint::zero()
+        );
+
+        // A huge maximum gap doesn't need any room of its own
+        let huge = Joltage {
+            min_gap: 1,
+            max_gap: usize::MAX,
+            device_offset: 3,
+        };
+        assert_eq!(part_1("1 2 4 8 10", &huge), Ok(2));
+        assert!(huge.check().is_ok());
+        let backwards = Joltage {
+            min_gap: 3,
+            max_gap: 1,
+            device_offset: 3,
+        };
+        assert!(backwards.check().is_err());
+    }
+
+    const SMALL_SAMPLE: &str = "16 10 15 5 1 11 7 19 6 12 4";
+
+    #[test]
+    fn test_chains() {
+        let arrangements = Arrangements::new(&parse_adapters(SMALL_SAMPLE), &Joltage::default());
+        let chains: Vec<Vec<usize>> = arrangements.chains().collect();
+
+        // The eight arrangements from the puzzle, in order
+        assert_eq!(chains.len(), 8);
+        assert_eq!(chains[0], vec![1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19]);
+        assert_eq!(chains[7], vec![1, 4, 7, 10, 12, 15, 16, 19]);
+        assert!(chains.windows(2).all(|pair| pair[0] < pair[1]));
+
+        for (k, chain) in chains.iter().enumerate() {
+            assert_eq!(arrangements.nth(&BigUint::from(k)).as_ref(), Some(chain));
+        }
+        assert_eq!(arrangements.nth(&BigUint::from(8u64)), None);
+    }
+
+    #[test]
+    fn test_prefix_comes_first() {
+        // The device at 5 can be reached from 3 or 4, so 1 3 is an
+        // arrangement as well as 1 3 4. The outlet can't reach 4.
+        let joltage = Joltage {
+            min_gap: 1,
+            max_gap: 3,
+            device_offset: 1,
+        };
+        let arrangements = Arrangements::new(&[1, 3, 4], &joltage);
+
+        assert_eq!(
+            arrangements.chains().collect::<Vec<Vec<usize>>>(),
+            vec![vec![1, 3], vec![1, 3, 4], vec![1, 4], vec![3], vec![3, 4]]
+        );
+        assert_eq!(arrangements.count(), &BigUint::from(5u64));
+    }
+
+    #[test]
+    fn test_large_nth_and_shortest() {
+        // Too many arrangements to list, but indexing doesn't need to
+        let adapters: Vec<usize> = (1..=300).collect();
+        let arrangements = Arrangements::new(&adapters, &Joltage::default());
+
+        let first = arrangements.nth(&BigUint::zero()).unwrap();
+        assert_eq!(first, adapters);
+        let last_index = arrangements.count().checked_sub(&BigUint::one()).unwrap();
+        let last = arrangements.nth(&last_index).unwrap();
+        // Skipping as much as possible as early as possible
+        assert_eq!(&last[..3], &[3, 6, 9]);
+        assert_eq!(arrangements.shortest().unwrap().len(), 100);
+    }
+
+    #[test]
+    fn test_sample() {
+        let arrangements = Arrangements::new(&parse_adapters(SMALL_SAMPLE), &Joltage::default());
+        let chains: Vec<Vec<usize>> = arrangements.chains().collect();
+        let mut rng = Rng::new(3);
+
+        let mut counts = vec![0; chains.len()];
+        for _ in 0..8000 {
+            let chain = arrangements.sample(&mut rng).unwrap();
+            counts[chains.iter().position(|c| *c == chain).unwrap()] += 1;
+        }
+        // Roughly 1000 each
+        assert!(counts.iter().all(|&count| count > 850 && count < 1150));
+
+        let impossible = Arrangements::new(&[5], &Joltage::default());
+        assert_eq!(impossible.sample(&mut rng), None);
+        assert_eq!(impossible.shortest(), None);
+        assert_eq!(impossible.chains().next(), None);
+    }
+
+    #[test]
+    fn test_clusters() {
+        let clusters = clusters(&parse_adapters(SMALL_SAMPLE), &Joltage::default());
+        let table: Vec<String> = clusters.iter().map(Cluster::to_string).collect();
+
+        assert_eq!(
+            table,
+            vec![
+                "0 -> 1",
+                "1 -> 1",
+                "4 5 6 7 -> 4",
+                "10 11 12 -> 2",
+                "15 -> 1",
+                "16 -> 1",
+                "19 -> 1",
+                "22 -> 1"
+            ]
+        );
+        assert!(breakdown(&clusters, &Joltage::default()).ends_with("\n\n4 * 2 = 8"));
+    }
+
+    #[test]
+    fn test_clusters_multiply_to_arrangements() {
+        let input = "28 33 18 42 31 14 46 20 48 47 24 23 49 45 19 38 39 11 1 32 25 35 8 17 7 9 \
+                     4 2 34 10 3";
+        let found = clusters(&parse_adapters(input), &Joltage::default());
+        let paths: Vec<String> = found
+            .iter()
+            .map(|cluster| cluster.paths.to_string())
+            .filter(|paths| paths != "1")
+            .collect();
+
+        // The prime factors of 19208 from the manual analysis
+        assert_eq!(paths, vec!["7", "7", "4", "2", "7", "7"]);
+        assert!(breakdown(&found, &Joltage::default()).ends_with("= 19208"));
+
+        // A run of consecutive joltages gives a tribonacci number: 0 to 8 is
+        // the ninth, after 1, 1, 2, 4, 7, 13, 24 and 44
+        let runs: Vec<String> = clusters(&(1..=8).collect::<Vec<usize>>(), &Joltage::default())
+            .iter()
+            .map(|cluster| cluster.paths.to_string())
+            .collect();
+        assert_eq!(runs, vec!["81", "1"]);
+
+        // Wider gaps merge more into each cluster, but the product still
+        // matches the direct count
+        let wide = Joltage {
+            min_gap: 1,
+            max_gap: 4,
+            device_offset: 4,
+        };
+        let adapters = parse_adapters(input);
+        assert!(breakdown(&clusters(&adapters, &wide), &wide)
+            .ends_with(&format!("= {}", count_arrangements(&adapters, &wide))));
     }
 }
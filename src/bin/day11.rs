@@ -1,8 +1,9 @@
+use aoc2020::automaton::{Automaton, Cycle, Grid, Neighbourhood, Rule};
 use aoc2020::read_string_input;
 use std::fmt;
-use std::str::FromStr;
+use std::ops::RangeInclusive;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Position {
     Floor,
     Empty,
@@ -34,264 +35,85 @@ impl fmt::Display for Position {
     }
 }
 
-#[derive(Debug, PartialEq)]
-struct SeatMap {
-    positions: Vec<Vec<Position>>,
-    rows: usize,
-    cols: usize,
-}
-
-impl SeatMap {
-
-    fn step(&mut self) {
-        let mut new_positions: Vec<Vec<Position>> = Vec::new();
-
-        for (y, row) in self.positions.iter().enumerate() {
-            let mut new_row: Vec<Position> = Vec::new();
-            for (x, position) in row.iter().enumerate() {
-                let neighbors = self.occupied_neighbors(x, y);
-
-                // If a seat is empty (L) and there are no occupied seats
-                // adjacent to it, the seat becomes occupied.
-                // If a seat is occupied (#) and four or more seats adjacent to
-                // it are also occupied, the seat becomes empty.
-                // Otherwise, the seat's state does not change.
-                match position {
-                    Position::Empty => {
-                        if neighbors == 0 {
-                            new_row.push(Position::Occupied);
-                        } else {
-                            new_row.push(Position::Empty);
-                        }
-                    },
-                    Position::Occupied => {
-                        if neighbors >= 4 {
-                            new_row.push(Position::Empty);
-                        } else {
-                            new_row.push(Position::Occupied);
-                        }
-                    },
-                    Position::Floor => {
-                        new_row.push(Position::Floor);
-                    },
-                    Position::Invalid => {},
-                }
-            }
-            new_positions.push(new_row);
-        }
-
-        self.positions = new_positions;
-        self.rows = self.positions.len();
-        self.cols = self.positions[0].len();
-    }
-
-    fn step_2(&mut self) {
-        let mut new_positions: Vec<Vec<Position>> = Vec::new();
-
-        for (y, row) in self.positions.iter().enumerate() {
-            let mut new_row: Vec<Position> = Vec::new();
-            for (x, position) in row.iter().enumerate() {
-                let neighbors = self.occupied_visible_seats(x, y);
-
-                // If a seat is empty (L) and there are no occupied seats
-                // adjacent to it, the seat becomes occupied.
-                // If a seat is occupied (#) and four or more seats adjacent to
-                // it are also occupied, the seat becomes empty.
-                // Otherwise, the seat's state does not change.
-                match position {
-                    Position::Empty => {
-                        if neighbors == 0 {
-                            new_row.push(Position::Occupied);
-                        } else {
-                            new_row.push(Position::Empty);
-                        }
-                    },
-                    Position::Occupied => {
-                        if neighbors >= 5 {
-                            new_row.push(Position::Empty);
-                        } else {
-                            new_row.push(Position::Occupied);
-                        }
-                    },
-                    Position::Floor => {
-                        new_row.push(Position::Floor);
-                    },
-                    Position::Invalid => {},
-                }
-            }
-            new_positions.push(new_row);
-        }
-
-        self.positions = new_positions;
-        self.rows = self.positions.len();
-        self.cols = self.positions[0].len();
-    }
-
-    fn occupied_neighbors(&self, x: usize, y: usize) -> usize {
-        let mut ydiff: &[i32] = &[-1, 0, 1];
-        let mut xdiff: &[i32] = &[-1, 0, 1];
-        if y == 0 {
-            ydiff = &[0, 1];
-        } else if y == self.rows - 1 {
-            ydiff = &[-1, 0];
-        }
-        if x == 0 {
-            xdiff = &[0, 1];
-        } else if x == self.cols - 1 {
-            xdiff = &[-1, 0];
-        }
-
-        let mut neighbors = 0;
-        for dy in ydiff {
-            for dx in xdiff {
-                let xi = (x as i32 + dx) as usize;
-                let yi = (y as i32 + dy) as usize;
-
-                if dx == &0 && dy == &0 {
-                    continue;
-                }
-
-                if self.positions[yi][xi] == Position::Occupied {
-                    neighbors += 1;
-                }
-            }
-        }
-
-        neighbors
-    }
-
-    fn occupied_visible_seats(&self, x: usize, y: usize) -> usize {
-        let mut ydiff: &[i32] = &[-1, 0, 1];
-        let mut xdiff: &[i32] = &[-1, 0, 1];
-
-        if y == 0 {
-            ydiff = &[0, 1];
-        } else if y == self.rows - 1 {
-            ydiff = &[-1, 0];
-        }
-        if x == 0 {
-            xdiff = &[0, 1];
-        } else if x == self.cols - 1 {
-            xdiff = &[-1, 0];
-        }
-
-        let mut neighbors = 0;
-
-        for dy in ydiff {
-            for dx in xdiff {
-                if dx == &0 && dy == &0 {
-                    continue;
-                }
-                // find closest neighbor in direction (dx, dy) and check it
-                let mut xcheck = x as i32 + dx;
-                let mut ycheck = y as i32 + dy;
-                let mut p = &self.positions[y][x];
-                while xcheck >= 0 && ycheck >= 0 && xcheck <= self.cols as i32 - 1 && ycheck <= self.rows as i32 - 1 {
-                    p = &self.positions[ycheck as usize][xcheck as usize];
-                    if p != &Position::Floor {
-                        break;
-                    }
-                    xcheck += dx;
-                    ycheck += dy;
-                }
-                if p == &Position::Occupied {
-                    neighbors += 1;
-                }
-            }
-        }
-
-        neighbors
-    }
-
-    fn occupied_seats(&self) -> usize {
-        let mut occupied = 0;
-
-        for row in &self.positions {
-            for col in row {
-                if col == &Position::Occupied {
-                    occupied += 1;
-                }
-            }
-        }
-        occupied
+impl Position {
+    fn is_floor(self) -> bool {
+        self == Position::Floor
     }
 }
 
-impl FromStr for SeatMap {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut positions: Vec<Vec<Position>> = Vec::new();
+type SeatMap = Grid<Position>;
 
-        for line in s.lines() {
-            let mut row: Vec<Position> = line.split("").map(Position::from).collect();
-            row.remove(0);
-            row.pop();
-            positions.push(row);
-        }
-
-        let rows = &positions.len();
-        let cols = &positions[0].len();
+fn parse_seat_map(s: &str) -> Result<SeatMap, String> {
+    Grid::new(
+        s.lines()
+            .map(|line| {
+                line.chars()
+                    .map(|c| Position::from(c.to_string().as_str()))
+                    .collect()
+            })
+            .collect(),
+    )
+}
 
-        Ok(SeatMap {
-            positions,
-            rows: *rows,
-            cols: *cols,
-        })
+/// If a seat is empty (L) and there are no occupied seats around it, the
+/// seat becomes occupied. If a seat is occupied (#) and `crowd` or more seats
+/// around it are also occupied, the seat becomes empty. Otherwise, the seat's
+/// state does not change.
+fn seating(crowd: usize) -> Rule<Position> {
+    // With a crowd of 0 nobody stays, so no number of neighbours survives
+    let survival = match crowd.checked_sub(1) {
+        Some(most) => 0..=most,
+        None => RangeInclusive::new(1, 0),
+    };
+    Rule {
+        live: Position::Occupied,
+        dead: Position::Empty,
+        birth: 0..=0,
+        survival,
     }
 }
 
-impl fmt::Display for SeatMap {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = self
-            .positions
-            .iter()
-            .map(|l| {
-                l.iter()
-                    .map(Position::to_string)
-                    .collect::<Vec<String>>()
-                    .join("")
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
+/// Part 1: people look at the seats next to them, and leave when four are
+/// taken.
+fn adjacent_seating(seat_map: SeatMap) -> Automaton<Position> {
+    Automaton::new(seat_map, Neighbourhood::Adjacent, seating(4)).unwrap()
+}
 
-        write!(f, "{}", s)
-    }
+/// Part 2: people look at the first seat they can see in each direction, and
+/// leave when five are taken.
+fn visible_seating(seat_map: SeatMap) -> Automaton<Position> {
+    Automaton::new(
+        seat_map,
+        Neighbourhood::LineOfSight(Position::is_floor),
+        seating(5),
+    )
+    .unwrap()
 }
 
 fn main() {
     let input = read_string_input("in-data/day11.txt");
 
-    println!("Part 1: {}", part_1(&input));
-    println!("Part 2: {}", part_2(&input));
+    match part_1(&input) {
+        Ok(occupied) => println!("Part 1: {}", occupied),
+        Err(cycle) => println!("Part 1: {}", cycle),
+    }
+    match part_2(&input) {
+        Ok(occupied) => println!("Part 2: {}", occupied),
+        Err(cycle) => println!("Part 2: {}", cycle),
+    }
 }
 
-fn part_1(input: &str) -> usize {
-    let mut seat_map = SeatMap::from_str(input).unwrap();
-    let mut seat_map_string = seat_map.to_string();
-
-    loop {
-        seat_map.step();
-        if seat_map.to_string() == seat_map_string {
-            break;
-        }
-        seat_map_string = seat_map.to_string();
-    }
-    seat_map.occupied_seats()
+fn part_1(input: &str) -> Result<usize, Cycle> {
+    occupied_when_stable(adjacent_seating(parse_seat_map(input).unwrap()))
 }
 
-fn part_2(input: &str) -> usize {
-    let mut seat_map = SeatMap::from_str(input).unwrap();
-    let mut seat_map_string = seat_map.to_string();
+fn part_2(input: &str) -> Result<usize, Cycle> {
+    occupied_when_stable(visible_seating(parse_seat_map(input).unwrap()))
+}
 
-    loop {
-        seat_map.step_2();
-        if seat_map.to_string() == seat_map_string {
-            break;
-        }
-        seat_map_string = seat_map.to_string();
-    }
-    seat_map.occupied_seats()
+fn occupied_when_stable(mut seats: Automaton<Position>) -> Result<usize, Cycle> {
+    let (_, seat_map) = seats.run_until_stable()?;
+    Ok(seat_map.count(Position::Occupied))
 }
 
 #[cfg(test)]
@@ -325,11 +147,11 @@ LLLLLLLLLL
 L.LLLLLL.L
 L.LLLLL.LL";
 
-        let seat_map = SeatMap::from_str(sample_input).unwrap();
+        let seat_map = parse_seat_map(sample_input).unwrap();
 
-        assert_eq!(seat_map.positions[0][0], Position::Empty);
-        assert_eq!(seat_map.positions[0][1], Position::Floor);
-        assert_eq!(seat_map.positions[9][9], Position::Empty);
+        assert_eq!(seat_map.get(0, 0), Position::Empty);
+        assert_eq!(seat_map.get(1, 0), Position::Floor);
+        assert_eq!(seat_map.get(9, 9), Position::Empty);
     }
 
     #[test]
@@ -337,14 +159,14 @@ L.LLLLL.LL";
         let sample_input = "...
 .##
 ...";
-        let seat_map = SeatMap::from_str(sample_input).unwrap();
-        assert_eq!(seat_map.occupied_neighbors(1, 1), 1);
+        let seats = adjacent_seating(parse_seat_map(sample_input).unwrap());
+        assert_eq!(seats.live_neighbours(1, 1), 1);
 
         let sample_input = "#..
 .L#
 ...";
-        let seat_map = SeatMap::from_str(sample_input).unwrap();
-        assert_eq!(seat_map.occupied_neighbors(1, 1), 2);
+        let seats = adjacent_seating(parse_seat_map(sample_input).unwrap());
+        assert_eq!(seats.live_neighbours(1, 1), 2);
     }
 
     #[test]
@@ -415,24 +237,24 @@ L.#.L..#..
 #.LLLLLL.L
 #.#L#L#.##";
 
-        let mut seat_map = SeatMap::from_str(sample_input).unwrap();
+        let mut seats = adjacent_seating(parse_seat_map(sample_input).unwrap());
 
-        seat_map.step();
-        assert_eq!(&seat_map, &SeatMap::from_str(sample_step_2).unwrap());
+        seats.step();
+        assert_eq!(seats.grid(), &parse_seat_map(sample_step_2).unwrap());
 
-        seat_map.step();
-        assert_eq!(&seat_map, &SeatMap::from_str(sample_step_3).unwrap());
+        seats.step();
+        assert_eq!(seats.grid(), &parse_seat_map(sample_step_3).unwrap());
 
-        seat_map.step();
-        assert_eq!(&seat_map, &SeatMap::from_str(sample_step_4).unwrap());
+        seats.step();
+        assert_eq!(seats.grid(), &parse_seat_map(sample_step_4).unwrap());
 
-        seat_map.step();
-        assert_eq!(&seat_map, &SeatMap::from_str(sample_step_5).unwrap());
+        seats.step();
+        assert_eq!(seats.grid(), &parse_seat_map(sample_step_5).unwrap());
 
-        seat_map.step();
-        assert_eq!(&seat_map, &SeatMap::from_str(sample_step_6).unwrap());
-        seat_map.step();
-        assert_eq!(&seat_map, &SeatMap::from_str(sample_step_6).unwrap());
+        seats.step();
+        assert_eq!(seats.grid(), &parse_seat_map(sample_step_6).unwrap());
+        seats.step();
+        assert_eq!(seats.grid(), &parse_seat_map(sample_step_6).unwrap());
     }
 
     #[test]
@@ -447,8 +269,61 @@ L.#.L..#..
 #........
 ...#.....";
 
-        let seat_map = SeatMap::from_str(sample_input).unwrap();
+        let seats = visible_seating(parse_seat_map(sample_input).unwrap());
+
+        assert_eq!(seats.live_neighbours(3, 4), 8);
+    }
+
+    #[test]
+    fn test_sample_input() {
+        let sample_input = "L.LL.LL.LL
+LLLLLLL.LL
+L.L.L..L..
+LLLL.LL.LL
+L.LL.LL.LL
+L.LLLLL.LL
+..L.L.....
+LLLLLLLLLL
+L.LLLLLL.L
+L.LLLLL.LL";
+
+        assert_eq!(part_1(sample_input), Ok(37));
+        assert_eq!(part_2(sample_input), Ok(26));
+    }
 
-        assert_eq!(seat_map.occupied_visible_seats(3, 4), 8);
+    #[test]
+    fn test_rounds_until_stable() {
+        let sample_input = "L.LL.LL.LL
+LLLLLLL.LL
+L.L.L..L..
+LLLL.LL.LL
+L.LL.LL.LL
+L.LLLLL.LL
+..L.L.....
+LLLLLLLLLL
+L.LLLLLL.L
+L.LLLLL.LL";
+        let mut seats = adjacent_seating(parse_seat_map(sample_input).unwrap());
+
+        assert_eq!(seats.run_until_stable().unwrap().0, 5);
+
+        // Two seats next to each other, with people who won't sit next to
+        // anyone, fill up and empty out in turn forever
+        let mut seats = Automaton::new(
+            parse_seat_map("LL").unwrap(),
+            Neighbourhood::Adjacent,
+            seating(1),
+        )
+        .unwrap();
+        assert_eq!(seats.run_until_stable().unwrap_err().period, 2);
+
+        // Nobody stays when anyone at all is a crowd
+        let mut seats = Automaton::new(
+            parse_seat_map("L").unwrap(),
+            Neighbourhood::Adjacent,
+            seating(0),
+        )
+        .unwrap();
+        assert_eq!(seats.run_until_stable().unwrap_err().period, 2);
     }
 }
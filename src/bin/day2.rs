@@ -26,12 +26,12 @@ fn read_input(filename: &str) -> Vec<Password> {
 }
 
 fn part_1(data: &[Password]) -> usize {
-    data.iter().filter(|p| check_password_validity(&p)).count()
+    data.iter().filter(|p| check_password_validity(p)).count()
 }
 
 fn part_2(data: &[Password]) -> usize {
     data.iter()
-        .filter(|p| check_password_validity_2(&p))
+        .filter(|p| check_password_validity_2(p))
         .count()
 }
 
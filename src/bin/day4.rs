@@ -122,7 +122,7 @@ fn parse_passport(data: &&str) -> Passport {
         pid: "".to_string(),
         cid: "".to_string(),
     };
-    for field in data.split(|c| c == '\n' || c == ' ') {
+    for field in data.split(['\n', ' ']) {
         let kv = field.split(':').collect::<vec::Vec<&str>>();
         match kv[0] {
             "byr" => passport.byr = kv[1].to_string(),
@@ -85,10 +85,9 @@ fn main() {
 /// > What is the highest seat ID on a boarding pass?
 ///
 /// Since I started by sorting all the boarding passes in `main()`, finding the
-/// one with the highest id is simply a matter of unwrapping the first element
-/// in the reversed vec.
+/// one with the highest id is simply a matter of unwrapping the last element.
 fn part_1(boarding_passes: &[BoardingPass]) -> u32 {
-    boarding_passes.iter().rev().next().unwrap().id
+    boarding_passes.last().unwrap().id
 }
 
 /// --- Part Two ---
@@ -133,8 +132,8 @@ fn part_2(boarding_passes: &[BoardingPass]) -> Option<u32> {
 fn parse_boarding_pass(pass: &str) -> BoardingPass {
     let re = Regex::new(r"^(?P<row>[FB]{7})(?P<col>[RL]{3})").unwrap();
     let captures = re.captures(pass).unwrap();
-    let row = translate_to_binary(&captures.name("row").unwrap().as_str());
-    let col = translate_to_binary(&captures.name("col").unwrap().as_str());
+    let row = translate_to_binary(captures.name("row").unwrap().as_str());
+    let col = translate_to_binary(captures.name("col").unwrap().as_str());
 
     BoardingPass {
         row,
@@ -160,7 +159,7 @@ mod tests {
     #[test]
     fn test_translate_row_col() {
         assert!(
-            parse_boarding_pass(&"BFFFBBFRRR")
+            parse_boarding_pass("BFFFBBFRRR")
                 == BoardingPass {
                     row: 70,
                     col: 7,
@@ -169,7 +168,7 @@ mod tests {
         );
 
         assert!(
-            parse_boarding_pass(&"FFFBBBFRRR")
+            parse_boarding_pass("FFFBBBFRRR")
                 == BoardingPass {
                     row: 14,
                     col: 7,
@@ -178,7 +177,7 @@ mod tests {
         );
 
         assert!(
-            parse_boarding_pass(&"BBFFBBFRLL")
+            parse_boarding_pass("BBFFBBFRLL")
                 == BoardingPass {
                     row: 102,
                     col: 4,
@@ -190,9 +189,9 @@ mod tests {
     #[test]
     fn test_boarding_pass_ordering() {
         let mut boarding_passes = vec![
-            parse_boarding_pass(&"BFFFBBFRRR"), // id: 567
-            parse_boarding_pass(&"FFFBBBFRRR"), // id: 119
-            parse_boarding_pass(&"BBFFBBFRLL"), // id: 820
+            parse_boarding_pass("BFFFBBFRRR"), // id: 567
+            parse_boarding_pass("FFFBBBFRRR"), // id: 119
+            parse_boarding_pass("BBFFBBFRLL"), // id: 820
         ];
 
         boarding_passes.sort();
@@ -200,9 +199,9 @@ mod tests {
         assert_eq!(
             boarding_passes,
             vec![
-                parse_boarding_pass(&"FFFBBBFRRR"), // id: 119
-                parse_boarding_pass(&"BFFFBBFRRR"), // id: 567
-                parse_boarding_pass(&"BBFFBBFRLL"), // id: 820
+                parse_boarding_pass("FFFBBBFRRR"), // id: 119
+                parse_boarding_pass("BFFFBBFRRR"), // id: 567
+                parse_boarding_pass("BBFFBBFRLL"), // id: 820
             ]
         );
     }
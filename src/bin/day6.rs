@@ -15,11 +15,11 @@ fn read_input(filename: &str) -> Vec<Vec<HashSet<String>>> {
         .collect()
 }
 
-fn part_1(data: &Vec<Vec<HashSet<String>>>) -> usize {
+fn part_1(data: &[Vec<HashSet<String>>]) -> usize {
     data.iter().map(union_group).map(|s| s.len()).sum()
 }
 
-fn part_2(data: &Vec<Vec<HashSet<String>>>) -> usize {
+fn part_2(data: &[Vec<HashSet<String>>]) -> usize {
     data.iter().map(intersect_group).map(|s| s.len()).sum()
 }
 
@@ -46,7 +46,7 @@ fn union_group(group: &Vec<HashSet<String>>) -> HashSet<String> {
 fn intersect_group(group: &Vec<HashSet<String>>) -> HashSet<String> {
     let mut set: HashSet<String> = "abcdefghijklmnopqrstuvwxyz"
         .split("")
-        .map(|c| String::from(c))
+        .map(String::from)
         .collect();
     for person in group {
         set = set.intersection(person).cloned().collect();
@@ -1,68 +1,100 @@
-use aoc2020::read_string_input;
+use aoc2020::bigint::BigUint;
+use aoc2020::json::Json;
+use aoc2020::{option_value, read_string_input};
 use regex::Regex;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::process;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 fn main() {
-    let contents = read_string_input("in-data/day7.txt");
-
-    println!("Part 1: {}", part_1(&contents));
-    println!("Part 2: {}", part_2(&contents));
-}
-
-fn part_1(contents: &str) -> usize {
-    let input = parse_input_part_1(&contents);
-
-    let mut items_to_check = vec!["shiny gold"];
-    let mut bags_that_can_hold_shiny_gold_bags = HashSet::new();
+    // Options:
+    //   --big           count with arbitrary precision instead of a usize
+    //   --input <file>  read rules from another file; `.json` files are
+    //                   imported from the format written by the json query
+    let mut big = false;
+    let mut filename = String::from("in-data/day7.txt");
+    let mut args: Vec<String> = vec![];
+    let mut options = env::args().skip(1);
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--big" => big = true,
+            "--input" => filename = option_value(options.next(), QUERY_USAGE),
+            _ => args.push(arg),
+        }
+    }
 
-    loop {
-        if items_to_check.is_empty() {
-            break;
+    let contents = read_string_input(&filename);
+    let rules = if filename.ends_with(".json") {
+        parse_json_rules(&contents)
+    } else {
+        parse_input_part_2(&contents)
+    };
+    let rules = match rules {
+        Ok(rules) => rules,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("Invalid rule set: {}", error);
+            }
+            process::exit(1);
         }
-        let item = items_to_check.remove(0);
-        if let Some(new_items) = input.get(item) {
-            for n in new_items {
-                items_to_check.push(n);
-                bags_that_can_hold_shiny_gold_bags.insert(n);
+    };
+
+    if !args.is_empty() {
+        match query(&rules, &args, big) {
+            Ok(output) => print!("{}", output),
+            Err(message) => {
+                eprintln!("{}", message);
+                process::exit(1);
             }
         }
+        return;
     }
-    bags_that_can_hold_shiny_gold_bags.len()
-}
 
-fn parse_input_part_1(contents: &str) -> HashMap<String, Vec<String>> {
-    let mut map: HashMap<String, Vec<String>> = HashMap::new();
-    let re = Regex::new(r"^(?P<key>\w+ \w+) bags contain (?P<val>[^.]+)").unwrap();
-
-    for rule in contents.lines() {
-        let captures = re.captures(rule).unwrap();
-        let vals = captures.name("val").unwrap().as_str();
-        for rule in find_content_colors(vals) {
-            let s = String::from(captures.name("key").unwrap().as_str());
-            match map.get_mut(&rule.color) {
-                Some(v) => {
-                    v.push(s);
-                }
-                None => {
-                    map.insert(rule.color, vec![s]);
-                }
-            }
+    println!("Part 1: {}", part_1(&rules));
+    let part_2 = if big {
+        part_2_big(&rules).map(|count| count.to_string())
+    } else {
+        part_2(&rules).map(|count| count.to_string())
+    };
+    match part_2 {
+        Ok(count) => println!("Part 2: {}", count),
+        Err(error) => {
+            eprintln!("Invalid rule set: {}", error);
+            process::exit(1);
         }
     }
+}
 
-    map
+fn part_1(rules: &HashMap<String, BagRule>) -> usize {
+    containers_of(&invert_rules(rules), "shiny gold").len()
 }
 
-fn find_content_colors(content: &str) -> Vec<BagContents> {
-    if content == "no other bags" {
-        return vec![];
+/// All colors that can eventually contain a `color` bag, given a map from
+/// each color to the bags that directly contain it.
+fn containers_of<'a>(
+    contained_in: &'a HashMap<String, Vec<String>>,
+    color: &str,
+) -> HashSet<&'a str> {
+    let mut items_to_check = VecDeque::from([color]);
+    let mut containers = HashSet::new();
+
+    while let Some(item) = items_to_check.pop_front() {
+        if let Some(new_items) = contained_in.get(item) {
+            for n in new_items {
+                // Only follow each bag once, so rule sets with cycles terminate
+                if containers.insert(n.as_str()) {
+                    items_to_check.push_back(n);
+                }
+            }
+        }
     }
-    content
-        .split(", ")
-        .map(|s| s.parse::<BagContents>().unwrap())
-        .collect()
+    containers
 }
 
 #[derive(Debug, Clone)]
@@ -75,10 +107,18 @@ impl FromStr for BagContents {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let re = Regex::new(r"^(?P<amount>\d+) (?P<color>\w+ \w+)").unwrap();
-        let captures = re.captures(s).unwrap();
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| Regex::new(r"^(?P<amount>\d+) (?P<color>\w+ \w+)").unwrap());
+        let captures = re
+            .captures(s)
+            .ok_or(format!("Invalid bag contents: {:?}", s))?;
         Ok(BagContents {
-            amount: captures.name("amount").unwrap().as_str().parse().unwrap(),
+            amount: captures
+                .name("amount")
+                .unwrap()
+                .as_str()
+                .parse()
+                .map_err(|_| format!("Invalid amount in bag contents: {:?}", s))?,
             color: String::from(captures.name("color").unwrap().as_str()),
         })
     }
@@ -88,15 +128,18 @@ impl FromStr for BagContents {
 struct BagRule {
     color: String,
     rules: Vec<BagContents>,
-    child_bags: Option<usize>,
 }
 
 impl FromStr for BagRule {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let re = Regex::new(r"^(?P<color>\w+ \w+) bags contain (?P<contents>[^.]+)").unwrap();
-        let captures = re.captures(s).unwrap();
+        // Compiled once, since large rule sets have hundreds of thousands of lines
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| {
+            Regex::new(r"^(?P<color>\w+ \w+) bags contain (?P<contents>[^.]+)").unwrap()
+        });
+        let captures = re.captures(s).ok_or(format!("Invalid bag rule: {:?}", s))?;
 
         let color = String::from(captures.name("color").unwrap().as_str());
         let rules = match captures.name("contents") {
@@ -107,62 +150,788 @@ impl FromStr for BagRule {
                     contents
                         .as_str()
                         .split(", ")
-                        .map(|s| s.parse::<BagContents>().unwrap())
-                        .collect()
+                        .map(|s| s.parse::<BagContents>())
+                        .collect::<Result<Vec<_>, _>>()?
                 }
             }
             None => vec![],
         };
 
-        Ok(BagRule {
-            color,
-            rules,
-            child_bags: None,
-        })
+        Ok(BagRule { color, rules })
+    }
+}
+
+impl fmt::Display for BagRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let contents = if self.rules.is_empty() {
+            String::from("no other bags")
+        } else {
+            self.rules
+                .iter()
+                .map(|bag| {
+                    let plural = if bag.amount == 1 { "" } else { "s" };
+                    format!("{} {} bag{}", bag.amount, bag.color, plural)
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        write!(f, "{} bags contain {}.", self.color, contents)
+    }
+}
+
+/// Everything that can be wrong with a rule set, as reported by
+/// `validate_rules` and `count_bags`.
+#[derive(Debug, Clone, PartialEq)]
+enum RuleError {
+    /// A line that could not be parsed as a bag rule.
+    Parse(String),
+    /// The same color has more than one rule.
+    DuplicateDefinition(String),
+    /// A color that has no rule of its own, along with the bag whose rule
+    /// mentioned it (if any).
+    UndefinedColor {
+        color: String,
+        referenced_by: Option<String>,
+    },
+    /// A bag that eventually contains itself. The path starts and ends with
+    /// the same color.
+    Cycle(Vec<String>),
+    /// The number of bags inside this color does not fit in a `usize`.
+    Overflow(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Parse(message) => write!(f, "{}", message),
+            RuleError::DuplicateDefinition(color) => {
+                write!(f, "{} bags are defined more than once", color)
+            }
+            RuleError::UndefinedColor {
+                color,
+                referenced_by: Some(parent),
+            } => write!(f, "{} bags contain undefined {} bags", parent, color),
+            RuleError::UndefinedColor {
+                color,
+                referenced_by: None,
+            } => write!(f, "{} bags are not defined", color),
+            RuleError::Cycle(path) => write!(f, "cycle: {}", path.join(" -> ")),
+            RuleError::Overflow(color) => write!(
+                f,
+                "{} bags contain too many bags to count (try --big)",
+                color
+            ),
+        }
     }
 }
 
-fn part_2(contents: &str) -> usize {
-    let mut rules = parse_input_part_2(&contents);
+fn part_2(rules: &HashMap<String, BagRule>) -> Result<usize, RuleError> {
+    Ok(count_bags(rules, "shiny gold")? - 1)
+}
 
-    count_bags(&mut rules, "shiny gold") - 1
+/// Part 2 with exact arithmetic, for rule sets whose answer overflows.
+fn part_2_big(rules: &HashMap<String, BagRule>) -> Result<BigUint, RuleError> {
+    let count = count_bags_big(rules, "shiny gold")?;
+
+    // Every successful count includes at least the outermost bag itself
+    Ok(count.checked_sub(&BigUint::one()).unwrap())
+}
+
+fn parse_input_part_2(input: &str) -> Result<HashMap<String, BagRule>, Vec<RuleError>> {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for line in input.lines() {
+        match line.parse::<BagRule>() {
+            Ok(rule) => rules.push(rule),
+            Err(message) => errors.push(RuleError::Parse(message)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    validate_rules(rules)
 }
 
-fn parse_input_part_2(input: &str) -> HashMap<String, BagRule> {
+/// Build the color -> rule map, checking that every color is defined exactly
+/// once, that every color mentioned in a rule has a rule of its own, and that
+/// no bag (transitively) contains itself.
+///
+/// All problems are reported, not just the first one.
+fn validate_rules(rules: Vec<BagRule>) -> Result<HashMap<String, BagRule>, Vec<RuleError>> {
+    let mut errors = Vec::new();
+    let mut order = Vec::new();
     let mut map = HashMap::new();
-    for rule in input.split('\n').map(|l| l.parse::<BagRule>().unwrap()) {
-        map.insert(String::from(&rule.color), rule);
+
+    for rule in rules {
+        if map.contains_key(&rule.color) {
+            errors.push(RuleError::DuplicateDefinition(rule.color));
+        } else {
+            order.push(rule.color.clone());
+            map.insert(rule.color.clone(), rule);
+        }
+    }
+
+    for color in &order {
+        for bag in &map[color].rules {
+            if !map.contains_key(&bag.color) {
+                errors.push(RuleError::UndefinedColor {
+                    color: bag.color.clone(),
+                    referenced_by: Some(color.clone()),
+                });
+            }
+        }
+    }
+
+    let mut finished: HashSet<&str> = HashSet::new();
+    for color in &order {
+        find_cycles(&map, color, &mut finished, &mut errors);
+    }
+
+    if errors.is_empty() {
+        Ok(map)
+    } else {
+        Err(errors)
     }
-    map
 }
 
-fn count_bags(rules: &mut HashMap<String, BagRule>, color: &str) -> usize {
-    let mut rules = rules;
-    let rule = rules.get(color).unwrap().clone();
-    1 + match rule.child_bags {
-        Some(child_bags) => child_bags,
-        None => {
-            let mut child_bags = 0;
-            for bag in rule.rules.iter() {
-                child_bags += bag.amount * count_bags(&mut rules, &bag.color);
+/// Depth-first walk from `start`, pushing a `RuleError::Cycle` for every back
+/// edge found.
+///
+/// The walk keeps its own stack rather than recursing, so arbitrarily deep
+/// rule sets can be checked.
+fn find_cycles<'a>(
+    rules: &'a HashMap<String, BagRule>,
+    start: &'a str,
+    finished: &mut HashSet<&'a str>,
+    errors: &mut Vec<RuleError>,
+) {
+    if finished.contains(start) || !rules.contains_key(start) {
+        return;
+    }
+    // The colors currently being visited, each with the index of the next
+    // bag in its contents to look at
+    let mut path: Vec<(&'a str, usize)> = vec![(start, 0)];
+    let mut on_path: HashSet<&'a str> = HashSet::new();
+    on_path.insert(start);
+
+    while let Some(&(color, next)) = path.last() {
+        let bag = match rules[color].rules.get(next) {
+            Some(bag) => bag.color.as_str(),
+            None => {
+                path.pop();
+                on_path.remove(color);
+                finished.insert(color);
+                continue;
             }
-            rules.get_mut(color).unwrap().child_bags = Some(child_bags);
-            child_bags
+        };
+        path.last_mut().unwrap().1 += 1;
+
+        if finished.contains(bag) || !rules.contains_key(bag) {
+            continue;
+        }
+        if on_path.contains(bag) {
+            let start = path.iter().position(|(c, _)| *c == bag).unwrap();
+            let mut cycle: Vec<String> = path[start..].iter().map(|(c, _)| c.to_string()).collect();
+            cycle.push(bag.to_string());
+            errors.push(RuleError::Cycle(cycle));
+        } else {
+            on_path.insert(bag);
+            path.push((bag, 0));
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The number types bag counts can be computed in.
+trait BagCount: Sized {
+    fn one() -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul_amount(&self, amount: usize) -> Option<Self>;
+}
 
-    #[test]
-    fn test_parse_input_part_1() {
-        let parsed = parse_input_part_1(&"bright red bags contain 2 light cyan bags");
+impl BagCount for usize {
+    fn one() -> Self {
+        1
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        usize::checked_add(*self, *other)
+    }
+
+    fn checked_mul_amount(&self, amount: usize) -> Option<Self> {
+        usize::checked_mul(*self, amount)
+    }
+}
+
+impl BagCount for BigUint {
+    fn one() -> Self {
+        BigUint::one()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul_amount(&self, amount: usize) -> Option<Self> {
+        Some(self * &BigUint::from(amount))
+    }
+}
+
+/// Count the bags needed for one `color` bag, including the bag itself.
+///
+/// Fails with `RuleError::Overflow` rather than wrapping if the count does
+/// not fit in a `usize`; see `count_bags_big` for those rule sets.
+fn count_bags(rules: &HashMap<String, BagRule>, color: &str) -> Result<usize, RuleError> {
+    Ok(count_bags_from(rules, &[color])?.remove(color).unwrap())
+}
+
+/// Like `count_bags`, but exact for arbitrarily large counts.
+fn count_bags_big(rules: &HashMap<String, BagRule>, color: &str) -> Result<BigUint, RuleError> {
+    Ok(count_bags_from(rules, &[color])?.remove(color).unwrap())
+}
+
+/// `count_bags` for every color in the rule set at once.
+fn count_all_bags<T: BagCount>(
+    rules: &HashMap<String, BagRule>,
+) -> Result<HashMap<&str, T>, RuleError> {
+    let colors: Vec<&str> = rules.keys().map(String::as_str).collect();
+    count_bags_from(rules, &colors)
+}
+
+/// Count the bags needed for every color inside the `roots` bags (and the
+/// roots themselves), each including the bag itself.
+///
+/// Bags are counted in `bottom_up` order: a bag is only counted once the
+/// counts of all its contents are known, so every count is computed exactly
+/// once and there is no recursion, however deep the rules go.
+fn count_bags_from<'a, T: BagCount>(
+    rules: &'a HashMap<String, BagRule>,
+    roots: &[&'a str],
+) -> Result<HashMap<&'a str, T>, RuleError> {
+    let BottomUp { order, reachable } = bottom_up(rules, roots, None)?;
+
+    let mut counted: HashMap<&str, T> = HashMap::with_capacity(order.len());
+    for item in order {
+        let mut count = T::one();
+        for bag in &rules[item].rules {
+            count = counted[bag.color.as_str()]
+                .checked_mul_amount(bag.amount)
+                .and_then(|bags| count.checked_add(&bags))
+                .ok_or_else(|| RuleError::Overflow(item.to_string()))?;
+        }
+        counted.insert(item, count);
+    }
+
+    if counted.len() < reachable {
+        // Whatever is left over is waiting on a cycle. A root that was not
+        // counted must itself be waiting on one.
+        let start = roots.iter().find(|c| !counted.contains_key(*c)).unwrap();
+        return Err(RuleError::Cycle(uncounted_cycle(rules, &counted, start)));
+    }
+    Ok(counted)
+}
+
+/// The bags inside the `roots` bags, and the roots themselves, in
+/// topological order from the bottom up.
+struct BottomUp<'a> {
+    /// Every bag comes after everything it directly contains. Bags that
+    /// contain a cycle, however deep down, can't be ordered and are left out.
+    order: Vec<&'a str>,
+    /// How many bags there are inside the roots, ordered or not.
+    reachable: usize,
+}
+
+/// Order the bags inside the `roots` bags from the bottom up, starting with
+/// the ones that contain nothing. A `stop` bag is treated as empty.
+///
+/// Each bag is only added once all of its contents have been, by counting
+/// how many contents it is still waiting on, so there is no recursion.
+fn bottom_up<'a>(
+    rules: &'a HashMap<String, BagRule>,
+    roots: &[&'a str],
+    stop: Option<&str>,
+) -> Result<BottomUp<'a>, RuleError> {
+    // Find everything inside the roots, which bags directly contain each of
+    // them, and how many contents each bag is still waiting on
+    let mut contained_in: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut waiting_on: HashMap<&str, usize> = HashMap::new();
+    let mut ready: Vec<&str> = vec![];
+    let mut reachable: HashSet<&str> = HashSet::new();
+    let mut items_to_check: Vec<(&str, Option<&str>)> = roots.iter().map(|c| (*c, None)).collect();
+
+    while let Some((item, parent)) = items_to_check.pop() {
+        if !reachable.insert(item) {
+            continue;
+        }
+        let rule = rules.get(item).ok_or_else(|| RuleError::UndefinedColor {
+            color: item.to_string(),
+            referenced_by: parent.map(String::from),
+        })?;
+        let contents = if stop == Some(item) {
+            &[]
+        } else {
+            &rule.rules[..]
+        };
+        if contents.is_empty() {
+            ready.push(item);
+        } else {
+            waiting_on.insert(item, contents.len());
+        }
+        for bag in contents {
+            contained_in.entry(&bag.color).or_default().push(item);
+            items_to_check.push((&bag.color, Some(item)));
+        }
+    }
+
+    let mut order = Vec::with_capacity(reachable.len());
+    while let Some(item) = ready.pop() {
+        order.push(item);
+        for parent in contained_in.get(item).map_or(&[][..], Vec::as_slice) {
+            let remaining = waiting_on.get_mut(parent).unwrap();
+            *remaining -= 1;
+            if *remaining == 0 {
+                ready.push(parent);
+            }
+        }
+    }
+    Ok(BottomUp {
+        order,
+        reachable: reachable.len(),
+    })
+}
+
+/// Follow uncounted bags down from `start` until one repeats, and return
+/// that loop. Every uncounted bag contains at least one other uncounted bag,
+/// so this always ends up going round a cycle.
+fn uncounted_cycle<T>(
+    rules: &HashMap<String, BagRule>,
+    counted: &HashMap<&str, T>,
+    start: &str,
+) -> Vec<String> {
+    let mut path = vec![start];
+    let mut position: HashMap<&str, usize> = HashMap::new();
+    position.insert(start, 0);
+
+    loop {
+        let next = rules[*path.last().unwrap()]
+            .rules
+            .iter()
+            .map(|bag| bag.color.as_str())
+            .find(|color| !counted.contains_key(color))
+            .unwrap();
+        if let Some(&start) = position.get(next) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|c| c.to_string()).collect();
+            cycle.push(next.to_string());
+            return cycle;
+        }
+        position.insert(next, path.len());
+        path.push(next);
+    }
+}
+
+// --- Export ---
+
+/// Render the rules as a Graphviz digraph with an edge from each bag to the
+/// bags it directly contains, labelled with the amount.
+///
+/// If `highlight` is given, that bag and everything it (transitively)
+/// contains is drawn in red.
+fn to_dot(rules: &HashMap<String, BagRule>, highlight: Option<&str>) -> String {
+    let mut highlighted = HashSet::new();
+    if let Some(color) = highlight {
+        let mut items_to_check = vec![color];
+        while let Some(item) = items_to_check.pop() {
+            if highlighted.insert(item) {
+                if let Some(rule) = rules.get(item) {
+                    items_to_check.extend(rule.rules.iter().map(|bag| bag.color.as_str()));
+                }
+            }
+        }
+    }
+    let style = |color: &str| {
+        if highlighted.contains(color) {
+            " [color=red, fontcolor=red]"
+        } else {
+            ""
+        }
+    };
+
+    let mut colors: Vec<&String> = rules.keys().collect();
+    colors.sort_unstable();
+
+    let mut dot = String::from("digraph bags {\n");
+    for color in &colors {
+        dot.push_str(&format!("    \"{}\"{};\n", color, style(color)));
+    }
+    for color in &colors {
+        for bag in &rules[*color].rules {
+            let edge_style = if highlighted.contains(color.as_str()) {
+                ", color=red, fontcolor=red"
+            } else {
+                ""
+            };
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+                color, bag.color, bag.amount, edge_style
+            ));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// The rules as a JSON object mapping each color to the list of bags it
+/// directly contains, sorted by color.
+///
+/// Fails if an amount is too big for a JSON number.
+fn to_json(rules: &HashMap<String, BagRule>) -> Result<Json, String> {
+    let mut colors: Vec<&String> = rules.keys().collect();
+    colors.sort_unstable();
+
+    let object = colors
+        .into_iter()
+        .map(|color| {
+            let contents = rules[color]
+                .rules
+                .iter()
+                .map(|bag| {
+                    let amount = i64::try_from(bag.amount).map_err(|_| {
+                        format!(
+                            "Can't export {} {} bags in {}: amounts in JSON go up to {}",
+                            bag.amount,
+                            bag.color,
+                            color,
+                            i64::MAX
+                        )
+                    })?;
+                    Ok(Json::Object(vec![
+                        (String::from("color"), Json::from(bag.color.as_str())),
+                        (String::from("amount"), Json::from(amount)),
+                    ]))
+                })
+                .collect::<Result<Vec<Json>, String>>()?;
+            Ok((color.clone(), Json::Array(contents)))
+        })
+        .collect::<Result<Vec<(String, Json)>, String>>()?;
+    Ok(Json::Object(object))
+}
+
+/// Whether `color` is two words, like the colors the puzzle input is made of.
+fn is_color(color: &str) -> bool {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^\w+ \w+$").unwrap())
+        .is_match(color)
+}
+
+/// Read rules back from the format written by `to_json`.
+fn from_json(json: &Json) -> Result<Vec<BagRule>, String> {
+    let invalid =
+        |message: &str, color: &str| format!("Invalid JSON rules for {:?}: {}", color, message);
+
+    json.as_object()
+        .ok_or("Invalid JSON rules: expected an object of colors")?
+        .iter()
+        .map(|(color, contents)| {
+            if !is_color(color) {
+                return Err(invalid("colors are two words", color));
+            }
+            let rules = contents
+                .as_array()
+                .ok_or_else(|| invalid("expected a list of contents", color))?
+                .iter()
+                .map(|bag| {
+                    let color = bag
+                        .get("color")
+                        .and_then(Json::as_str)
+                        .filter(|color| is_color(color))
+                        .ok_or_else(|| invalid("contents need a two word \"color\"", color))?;
+                    let amount = bag
+                        .get("amount")
+                        .and_then(Json::as_i64)
+                        .filter(|amount| *amount > 0)
+                        .and_then(|amount| usize::try_from(amount).ok())
+                        .ok_or_else(|| invalid("contents need a positive \"amount\"", color))?;
+                    Ok(BagContents {
+                        amount,
+                        color: color.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(BagRule {
+                color: color.clone(),
+                rules,
+            })
+        })
+        .collect()
+}
+
+/// Parse and validate rules exported by `to_json`.
+fn parse_json_rules(input: &str) -> Result<HashMap<String, BagRule>, Vec<RuleError>> {
+    let rules = input
+        .parse::<Json>()
+        .and_then(|json| from_json(&json))
+        .map_err(|message| vec![RuleError::Parse(message)])?;
+    validate_rules(rules)
+}
+
+// --- Queries ---
+//
+// Running day7 with arguments answers questions about any color instead of
+// solving the puzzle:
+//
+//   containers <color>           bags that can eventually contain <color>
+//   count <color>                bags inside one <color> bag
+//   counts                       bags inside every color
+//   tree <color>                 everything inside <color>, with amounts
+//   chain <outer> <inner>        shortest and longest containment chains
+//   top                          bags that no other bag contains
+//   dot [<color>]                Graphviz graph, highlighting <color>
+//   json                         rules as JSON, readable with --input
+//
+// Colors are always two words, so they can be given quoted or unquoted.
+
+const QUERY_USAGE: &str = "Usage: day7 [--big] [--input <file>] [containers <color> | count <color> | counts | tree <color> | chain <outer color> <inner color> | top | dot [<color>] | json]";
+
+/// Turn the rules around, mapping each color to the colors that directly
+/// contain it.
+fn invert_rules(rules: &HashMap<String, BagRule>) -> HashMap<String, Vec<String>> {
+    let mut contained_in: HashMap<String, Vec<String>> = HashMap::new();
+    for rule in rules.values() {
+        for bag in &rule.rules {
+            contained_in
+                .entry(bag.color.clone())
+                .or_default()
+                .push(rule.color.clone());
+        }
+    }
+    contained_in
+}
+
+/// Colors that are not inside any other bag, sorted.
+fn top_level_bags(rules: &HashMap<String, BagRule>) -> Vec<&str> {
+    let contained_in = invert_rules(rules);
+    let mut top: Vec<&str> = rules
+        .keys()
+        .filter(|color| !contained_in.contains_key(*color))
+        .map(String::as_str)
+        .collect();
+    top.sort_unstable();
+    top
+}
+
+/// Render everything inside a `color` bag, one bag per line, indented by
+/// depth and prefixed with the amount inside its parent.
+///
+/// The walk keeps its own stack rather than recursing, so arbitrarily deep
+/// rule sets can be rendered.
+fn containment_tree(rules: &HashMap<String, BagRule>, color: &str) -> Result<String, RuleError> {
+    let mut tree = String::new();
+    // Bags still to write, each with its amount and depth, with the next one
+    // on top
+    let mut stack: Vec<(&str, Option<usize>, usize)> = vec![(color, None, 0)];
+
+    while let Some((color, amount, depth)) = stack.pop() {
+        let rule = rules.get(color).ok_or_else(|| RuleError::UndefinedColor {
+            color: color.to_string(),
+            referenced_by: None,
+        })?;
+        tree.push_str(&"  ".repeat(depth));
+        if let Some(amount) = amount {
+            tree.push_str(&format!("{} ", amount));
+        }
+        tree.push_str(color);
+        tree.push('\n');
+        for bag in rule.rules.iter().rev() {
+            stack.push((&bag.color, Some(bag.amount), depth + 1));
+        }
+    }
+    Ok(tree)
+}
+
+/// The chain of bags from `outer` down to `inner` passing through the fewest
+/// bags, found by breadth-first search.
+fn shortest_chain(
+    rules: &HashMap<String, BagRule>,
+    outer: &str,
+    inner: &str,
+) -> Option<Vec<String>> {
+    let mut came_from: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(outer);
+
+    while let Some(color) = queue.pop_front() {
+        if color == inner {
+            let mut chain = vec![inner.to_string()];
+            let mut current = inner;
+            while current != outer {
+                current = came_from[current];
+                chain.push(current.to_string());
+            }
+            chain.reverse();
+            return Some(chain);
+        }
+        for bag in rules.get(color).map_or(&[][..], |rule| &rule.rules) {
+            if bag.color != outer && !came_from.contains_key(bag.color.as_str()) {
+                came_from.insert(&bag.color, color);
+                queue.push_back(&bag.color);
+            }
+        }
+    }
+    None
+}
+
+/// The chain of bags from `outer` down to `inner` passing through the most
+/// bags, or `None` if there isn't one or the rules are incomplete. Bags that
+/// are part of a cycle are never on it.
+///
+/// Like `count_bags_from`, this works in `bottom_up` order, so there is no
+/// recursion, and each bag only remembers the length of its longest chain
+/// and the next bag on it.
+fn longest_chain(
+    rules: &HashMap<String, BagRule>,
+    outer: &str,
+    inner: &str,
+) -> Option<Vec<String>> {
+    let order = bottom_up(rules, &[outer], Some(inner)).ok()?.order;
+
+    // The length of the longest chain down to `inner` from each bag that has
+    // one, and the next bag on it
+    let mut longest: HashMap<&str, (usize, Option<&str>)> = HashMap::new();
+    for item in order {
+        let best = if item == inner {
+            Some((1, None))
+        } else {
+            let mut best: Option<(usize, Option<&str>)> = None;
+            for bag in &rules[item].rules {
+                if let Some(&(length, _)) = longest.get(bag.color.as_str()) {
+                    if best.is_none_or(|(best, _)| length + 1 > best) {
+                        best = Some((length + 1, Some(&bag.color)));
+                    }
+                }
+            }
+            best
+        };
+        if let Some(best) = best {
+            longest.insert(item, best);
+        }
+    }
+
+    let mut chain = vec![outer.to_string()];
+    let mut next = longest.get(outer)?.1;
+    while let Some(color) = next {
+        chain.push(color.to_string());
+        next = longest[color].1;
+    }
+    Some(chain)
+}
+
+/// Split command line words into two-word colors.
+fn parse_colors(words: &[String]) -> Result<Vec<String>, String> {
+    let words: Vec<&str> = words.iter().flat_map(|w| w.split_whitespace()).collect();
+    if !words.len().is_multiple_of(2) {
+        return Err(format!(
+            "Colors are two words each, got: {}",
+            words.join(" ")
+        ));
+    }
+    Ok(words.chunks(2).map(|pair| pair.join(" ")).collect())
+}
+
+fn require_color(rules: &HashMap<String, BagRule>, color: &str) -> Result<(), String> {
+    if rules.contains_key(color) {
+        Ok(())
+    } else {
+        Err(RuleError::UndefinedColor {
+            color: color.to_string(),
+            referenced_by: None,
+        }
+        .to_string())
+    }
+}
 
-        assert!(parsed.contains_key("light cyan"));
+/// Answer a query given as command line arguments, returning the text to
+/// print.
+fn query(rules: &HashMap<String, BagRule>, args: &[String], big: bool) -> Result<String, String> {
+    let (command, rest) = args.split_first().ok_or(QUERY_USAGE)?;
+    let colors = parse_colors(rest)?;
+    for color in &colors {
+        require_color(rules, color)?;
     }
 
+    match (command.as_str(), colors.as_slice()) {
+        ("containers", [color]) => {
+            let contained_in = invert_rules(rules);
+            let mut containers: Vec<&str> =
+                containers_of(&contained_in, color).into_iter().collect();
+            containers.sort_unstable();
+            Ok(format!(
+                "{}\n{} bags can contain {} bags\n",
+                containers.join("\n"),
+                containers.len(),
+                color
+            ))
+        }
+        ("count", [color]) => {
+            let count = if big {
+                count_bags_big(rules, color)
+                    .map(|count| count.checked_sub(&BigUint::one()).unwrap().to_string())
+            } else {
+                count_bags(rules, color).map(|count| (count - 1).to_string())
+            };
+            Ok(format!("{}\n", count.map_err(|e| e.to_string())?))
+        }
+        ("counts", []) => {
+            let counts: Vec<(&str, String)> = if big {
+                count_all_bags::<BigUint>(rules).map(|counts| {
+                    counts
+                        .into_iter()
+                        .map(|(color, count)| {
+                            (
+                                color,
+                                count.checked_sub(&BigUint::one()).unwrap().to_string(),
+                            )
+                        })
+                        .collect()
+                })
+            } else {
+                count_all_bags::<usize>(rules).map(|counts| {
+                    counts
+                        .into_iter()
+                        .map(|(color, count)| (color, (count - 1).to_string()))
+                        .collect()
+                })
+            }
+            .map_err(|e| e.to_string())?;
+            let mut lines: Vec<String> = counts
+                .into_iter()
+                .map(|(color, count)| format!("{}: {}", color, count))
+                .collect();
+            lines.sort_unstable();
+            Ok(lines.join("\n") + "\n")
+        }
+        ("tree", [color]) => containment_tree(rules, color).map_err(|e| e.to_string()),
+        ("chain", [outer, inner]) => match (
+            shortest_chain(rules, outer, inner),
+            longest_chain(rules, outer, inner),
+        ) {
+            (Some(shortest), Some(longest)) => Ok(format!(
+                "shortest: {}\nlongest: {}\n",
+                shortest.join(" -> "),
+                longest.join(" -> ")
+            )),
+            _ => Err(format!("{} bags can not contain {} bags", outer, inner)),
+        },
+        ("top", []) => Ok(top_level_bags(rules).join("\n") + "\n"),
+        ("dot", []) => Ok(to_dot(rules, None)),
+        ("dot", [color]) => Ok(to_dot(rules, Some(color))),
+        ("json", []) => Ok(format!("{:#}\n", to_json(rules)?)),
+        _ => Err(QUERY_USAGE.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
     #[test]
     fn test_sample_input_part_1() {
         let sample_input = "light red bags contain 1 bright white bag, 2 muted yellow bags.
@@ -175,7 +944,7 @@ vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags.";
 
-        assert_eq!(part_1(&sample_input), 4);
+        assert_eq!(part_1(&parse_input_part_2(sample_input).unwrap()), 4);
     }
 
     #[test]
@@ -187,15 +956,15 @@ dark yellow bags contain 2 dark green bags.
 dark green bags contain 2 dark blue bags.
 dark blue bags contain 2 dark violet bags.
 dark violet bags contain no other bags.";
-        let mut rules = parse_input_part_2(&sample_input);
+        let rules = parse_input_part_2(sample_input).unwrap();
 
-        assert_eq!(count_bags(&mut rules, "dark violet"), 1);
-        assert_eq!(count_bags(&mut rules, "dark blue"), 3);
-        assert_eq!(count_bags(&mut rules, "dark green"), 7);
-        assert_eq!(count_bags(&mut rules, "dark yellow"), 15);
-        assert_eq!(count_bags(&mut rules, "dark orange"), 31);
-        assert_eq!(count_bags(&mut rules, "dark red"), 63);
-        assert_eq!(count_bags(&mut rules, "shiny gold"), 127);
+        assert_eq!(count_bags(&rules, "dark violet"), Ok(1));
+        assert_eq!(count_bags(&rules, "dark blue"), Ok(3));
+        assert_eq!(count_bags(&rules, "dark green"), Ok(7));
+        assert_eq!(count_bags(&rules, "dark yellow"), Ok(15));
+        assert_eq!(count_bags(&rules, "dark orange"), Ok(31));
+        assert_eq!(count_bags(&rules, "dark red"), Ok(63));
+        assert_eq!(count_bags(&rules, "shiny gold"), Ok(127));
     }
 
     #[test]
@@ -208,6 +977,325 @@ dark green bags contain 2 dark blue bags.
 dark blue bags contain 2 dark violet bags.
 dark violet bags contain no other bags.";
 
-        assert_eq!(part_2(&sample_input), 126);
+        assert_eq!(part_2(&parse_input_part_2(sample_input).unwrap()), Ok(126));
+    }
+
+    #[test]
+    fn test_validate_cycle() {
+        let sample_input = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 1 dark blue bag.
+dark blue bags contain 3 shiny gold bags, 1 faded blue bag.
+faded blue bags contain no other bags.";
+
+        assert_eq!(
+            parse_input_part_2(sample_input).unwrap_err(),
+            vec![RuleError::Cycle(vec![
+                String::from("shiny gold"),
+                String::from("dark red"),
+                String::from("dark blue"),
+                String::from("shiny gold"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_validate_undefined_and_duplicate() {
+        let sample_input = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 1 dark blue bag.
+shiny gold bags contain no other bags.";
+
+        assert_eq!(
+            parse_input_part_2(sample_input).unwrap_err(),
+            vec![
+                RuleError::DuplicateDefinition(String::from("shiny gold")),
+                RuleError::UndefinedColor {
+                    color: String::from("dark blue"),
+                    referenced_by: Some(String::from("dark red")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_parse_error() {
+        let errors = parse_input_part_2("shiny gold bags contain lots of bags.").unwrap_err();
+
+        assert!(matches!(errors[0], RuleError::Parse(_)));
+    }
+
+    #[test]
+    fn test_count_bags_without_validation() {
+        let mut rules = HashMap::new();
+        for line in "shiny gold bags contain 2 dark red bags.
+dark red bags contain 1 shiny gold bag."
+            .lines()
+        {
+            let rule = line.parse::<BagRule>().unwrap();
+            rules.insert(rule.color.clone(), rule);
+        }
+
+        assert_eq!(
+            count_bags(&rules, "shiny gold"),
+            Err(RuleError::Cycle(vec![
+                String::from("shiny gold"),
+                String::from("dark red"),
+                String::from("shiny gold"),
+            ]))
+        );
+        rules.remove("shiny gold");
+        assert_eq!(
+            count_bags(&rules, "dark red"),
+            Err(RuleError::UndefinedColor {
+                color: String::from("shiny gold"),
+                referenced_by: Some(String::from("dark red")),
+            })
+        );
+    }
+
+    #[test]
+    fn test_count_bags_overflow() {
+        // Each level doubles the count, so 70 levels is far more than a u64
+        let mut sample_input = String::from("shiny gold bags contain 2 dark level0 bags.\n");
+        for level in 0..70 {
+            sample_input.push_str(&format!(
+                "dark level{} bags contain 2 dark level{} bags.\n",
+                level,
+                level + 1
+            ));
+        }
+        sample_input.push_str("dark level70 bags contain no other bags.");
+
+        let rules = parse_input_part_2(&sample_input).unwrap();
+        assert_eq!(
+            count_bags(&rules, "shiny gold"),
+            Err(RuleError::Overflow(String::from("dark level6")))
+        );
+        assert_eq!(count_bags(&rules, "dark level10"), Ok((1 << 61) - 1));
+        // 2^72 - 2
+        assert_eq!(
+            part_2_big(&rules).unwrap().to_string(),
+            "4722366482869645213694"
+        );
+    }
+
+    const SAMPLE_INPUT: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+
+    #[test]
+    fn test_top_level_bags() {
+        let rules = parse_input_part_2(SAMPLE_INPUT).unwrap();
+
+        assert_eq!(top_level_bags(&rules), vec!["dark orange", "light red"]);
+    }
+
+    #[test]
+    fn test_containment_tree() {
+        let rules = parse_input_part_2(SAMPLE_INPUT).unwrap();
+
+        assert_eq!(
+            containment_tree(&rules, "shiny gold").unwrap(),
+            "shiny gold
+  1 dark olive
+    3 faded blue
+    4 dotted black
+  2 vibrant plum
+    5 faded blue
+    6 dotted black
+"
+        );
+    }
+
+    #[test]
+    fn test_chains() {
+        let rules = parse_input_part_2(SAMPLE_INPUT).unwrap();
+
+        assert_eq!(
+            shortest_chain(&rules, "light red", "faded blue").unwrap(),
+            vec!["light red", "muted yellow", "faded blue"]
+        );
+        assert_eq!(
+            longest_chain(&rules, "light red", "faded blue").unwrap(),
+            vec![
+                "light red",
+                "bright white",
+                "shiny gold",
+                "dark olive",
+                "faded blue"
+            ]
+        );
+        assert_eq!(shortest_chain(&rules, "faded blue", "light red"), None);
+        assert_eq!(longest_chain(&rules, "faded blue", "light red"), None);
+        assert_eq!(
+            longest_chain(&rules, "shiny gold", "shiny gold").unwrap(),
+            vec!["shiny gold"]
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let rules = parse_input_part_2(SAMPLE_INPUT).unwrap();
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<String>>();
+
+        assert_eq!(
+            query(&rules, &args("containers shiny gold"), false),
+            Ok(String::from(
+                "bright white\ndark orange\nlight red\nmuted yellow\n4 bags can contain shiny gold bags\n"
+            ))
+        );
+        assert_eq!(
+            query(&rules, &args("count shiny gold"), false),
+            Ok(String::from("32\n"))
+        );
+        assert_eq!(
+            query(&rules, &args("count shiny gold"), true),
+            Ok(String::from("32\n"))
+        );
+        assert_eq!(
+            query(&rules, &args("chain shiny gold dotted black"), false),
+            Ok(String::from(
+                "shortest: shiny gold -> dark olive -> dotted black\nlongest: shiny gold -> dark olive -> dotted black\n"
+            ))
+        );
+        assert_eq!(
+            query(
+                &rules,
+                &[String::from("containers"), String::from("shiny gold")],
+                false
+            ),
+            query(&rules, &args("containers shiny gold"), false)
+        );
+        assert!(query(&rules, &args("count shiny"), false).is_err());
+        assert!(query(&rules, &args("count plain beige"), false).is_err());
+        assert!(query(&rules, &args("frobnicate"), false).is_err());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let rules = parse_input_part_2(
+            "shiny gold bags contain 2 dark red bags.
+dark red bags contain no other bags.
+light red bags contain 1 shiny gold bag.",
+        )
+        .unwrap();
+
+        assert_eq!(
+            to_dot(&rules, Some("shiny gold")),
+            r#"digraph bags {
+    "dark red" [color=red, fontcolor=red];
+    "light red";
+    "shiny gold" [color=red, fontcolor=red];
+    "light red" -> "shiny gold" [label="1"];
+    "shiny gold" -> "dark red" [label="2", color=red, fontcolor=red];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let rules = parse_input_part_2(SAMPLE_INPUT).unwrap();
+        let json = to_json(&rules).unwrap();
+
+        assert_eq!(
+            json.get("bright white").unwrap().to_string(),
+            r#"[{"color":"shiny gold","amount":1}]"#
+        );
+        assert_eq!(json.get("faded blue").unwrap().to_string(), "[]");
+
+        let imported = parse_json_rules(&format!("{:#}", json)).unwrap();
+        assert_eq!(to_json(&imported), Ok(json));
+        assert_eq!(part_1(&imported), 4);
+        assert_eq!(part_2(&imported), Ok(32));
+
+        let huge = parse_input_part_2(&format!(
+            "shiny gold bags contain {} dark red bags.\ndark red bags contain no other bags.",
+            usize::MAX
+        ))
+        .unwrap();
+        assert_eq!(
+            to_json(&huge),
+            Err(format!(
+                "Can't export {} dark red bags in shiny gold: amounts in JSON go up to {}",
+                usize::MAX,
+                i64::MAX
+            ))
+        );
+    }
+
+    #[test]
+    fn test_invalid_json_rules() {
+        assert!(parse_json_rules("[]").is_err());
+        assert!(
+            parse_json_rules(r#"{"red": [], "shiny gold": [{"color": "red", "amount": 1}]}"#)
+                .is_err()
+        );
+        assert!(parse_json_rules(r#"{"shiny gold": [{"color": "red", "amount": 1}]}"#).is_err());
+        assert!(parse_json_rules(r#"{"shiny gold": [{"color": "dark red"}]}"#).is_err());
+        assert!(
+            parse_json_rules(r#"{"shiny gold": [{"color": "dark red", "amount": 0}]}"#).is_err()
+        );
+        assert_eq!(
+            parse_json_rules(r#"{"shiny gold": [{"color": "dark red", "amount": 1}]}"#)
+                .unwrap_err(),
+            vec![RuleError::UndefinedColor {
+                color: String::from("dark red"),
+                referenced_by: Some(String::from("shiny gold")),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rule_to_string() {
+        for line in SAMPLE_INPUT.lines() {
+            assert_eq!(line.parse::<BagRule>().unwrap().to_string(), line);
+        }
+    }
+
+    #[test]
+    fn test_count_all_bags() {
+        let rules = parse_input_part_2(SAMPLE_INPUT).unwrap();
+        let counts = count_all_bags::<usize>(&rules).unwrap();
+
+        assert_eq!(counts.len(), 9);
+        assert_eq!(counts["faded blue"], 1);
+        assert_eq!(counts["dark olive"], 8);
+        assert_eq!(counts["shiny gold"], 33);
+        assert_eq!(counts["light red"], 187);
+    }
+
+    #[test]
+    fn test_count_deep_rules() {
+        // Far deeper than a recursive count could go on the default stack
+        let depth = 100_000;
+        let rules = (0..=depth)
+            .map(|level| BagRule {
+                color: format!("level {}", level),
+                rules: if level == depth {
+                    vec![]
+                } else {
+                    vec![BagContents {
+                        amount: 1,
+                        color: format!("level {}", level + 1),
+                    }]
+                },
+            })
+            .collect();
+
+        let rules = validate_rules(rules).unwrap();
+        assert_eq!(count_bags(&rules, "level 0"), Ok(depth + 1));
+        assert_eq!(
+            longest_chain(&rules, "level 0", &format!("level {}", depth))
+                .unwrap()
+                .len(),
+            depth + 1
+        );
+        assert_eq!(count_all_bags::<usize>(&rules).unwrap().len(), depth + 1);
     }
 }
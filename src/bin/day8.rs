@@ -1,174 +1,116 @@
-use aoc2020::read_string_input;
-use std::collections::HashSet;
-use std::str::FromStr;
+use aoc2020::vm::analysis::Analysis;
+use aoc2020::vm::asm::{assemble, disassemble};
+use aoc2020::vm::debugger::Debugger;
+use aoc2020::vm::repair::{repair, Repair};
+use aoc2020::vm::trace::{run_with, Coverage, Trace};
+use aoc2020::vm::{Instruction, Machine};
+use aoc2020::{option_value, read_string_input};
+use std::env;
+use std::io;
+use std::process;
+
+const USAGE: &str =
+    "Usage: day8 [--input <file>] [--max-steps <n>] [disasm | analyse | debug | trace [--json] | coverage [--json]]";
 
-#[derive(Debug)]
-struct ProgramState {
-    acc: i32,
-    next_instruction: i32,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-enum InstructionCode {
-    Acc,
-    Jmp,
-    Nop,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-struct Instruction {
-    code: InstructionCode,
-    val: i32,
-}
-
-impl FromStr for Instruction {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.splitn(2, ' ').collect();
-        let val = parts[1].parse::<i32>().unwrap();
-        let code = match parts[0] {
-            "acc" => InstructionCode::Acc,
-            "nop" => InstructionCode::Nop,
-            "jmp" => InstructionCode::Jmp,
-            _ => return Err("Invalid instruction".to_string()),
-        };
-        Ok(Instruction { code, val })
-    }
-}
-
-impl Instruction {
-    fn execute(&self, state: &ProgramState) -> ProgramState {
-        match self.code {
-            InstructionCode::Acc => ProgramState {
-                acc: state.acc + self.val,
-                next_instruction: state.next_instruction + 1,
-            },
-            InstructionCode::Nop => ProgramState {
-                acc: state.acc,
-                next_instruction: state.next_instruction + 1,
-            },
-            InstructionCode::Jmp => ProgramState {
-                acc: state.acc,
-                next_instruction: state.next_instruction + self.val,
-            },
+fn main() {
+    // Options:
+    //   --input <file>  run another program, written in the puzzle format or
+    //                   with the labels and comments the assembler supports
+    //   --max-steps <n> stop trace, coverage and debug after n instructions
+    // Commands:
+    //   disasm          list the program with addresses instead of running it
+    //   analyse         basic blocks, unreachable code, out of bounds jumps
+    //                   and loops, all without running the program
+    //   debug           step through the program, reading commands from stdin
+    //   trace           every executed instruction, with acc before and after
+    //   coverage        how many times each instruction ran
+    //                   (trace and coverage print JSON when given --json)
+    let mut filename = String::from("in-data/day8.txt");
+    let mut step_limit = None;
+    let mut args: Vec<String> = vec![];
+    let mut options = env::args().skip(1);
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--input" => filename = option_value(options.next(), USAGE),
+            "--max-steps" => step_limit = Some(option_value(options.next(), USAGE)),
+            _ => args.push(arg),
         }
     }
-}
-
-fn main() {
-    let contents = read_string_input("in-data/day8.txt");
-    let program = parse_input(&contents);
 
-    println!("Part 1: {}", part_1(&program));
-    println!("Part 2: {}", part_2(&program));
-}
-
-fn part_1(program: &Vec<Instruction>) -> i32 {
-    let mut visited_addresses: HashSet<i32> = HashSet::new();
-    let mut pointer = ProgramState {
-        acc: 0,
-        next_instruction: 0,
+    let program = match assemble(&read_string_input(&filename)) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("{}: {}", filename, error);
+            process::exit(1);
+        }
+    };
+    let machine = || {
+        let mut machine = Machine::new(program.clone());
+        machine.set_step_limit(step_limit);
+        machine
     };
 
-    while !visited_addresses.contains(&pointer.next_instruction) {
-        visited_addresses.insert(pointer.next_instruction);
-        let instruction = &program[pointer.next_instruction as usize];
-        pointer = instruction.execute(&pointer);
-    }
-    pointer.acc
-}
-
-fn part_2(program: &Vec<Instruction>) -> i32 {
-    let mut program = program.clone();
-    let mut candidate_instructions: Vec<usize> = Vec::new();
-    for (index, instruction) in program.iter().enumerate() {
-        match instruction.code {
-            InstructionCode::Acc => {
-                continue;
-            }
-            _ => {
-                candidate_instructions.push(index);
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<&str>>()
+        .as_slice()
+    {
+        [] => {
+            println!("Part 1: {}", part_1(&program));
+            match part_2(&program) {
+                Ok(repair) => println!("Part 2: {} ({})", repair.acc, repair),
+                Err(message) => println!("Part 2: {}", message),
             }
         }
-    }
-
-    for index in candidate_instructions {
-        // modify instruction
-        match program[index].code {
-            InstructionCode::Nop => program[index].code = InstructionCode::Jmp,
-            InstructionCode::Jmp => program[index].code = InstructionCode::Nop,
-            _ => {}
+        ["disasm"] => print!("{}", disassemble(&program)),
+        ["analyse"] => println!("{}", Analysis::new(&program)),
+        ["trace"] => {
+            // Print as it runs rather than recording everything first
+            let halt = run_with(&mut machine(), |step| println!("{}", step));
+            println!("Halted: {}", halt);
         }
-
-        // try to run program
-        let mut visited_addresses: HashSet<i32> = HashSet::new();
-        let mut pointer = ProgramState {
-            acc: 0,
-            next_instruction: 0,
-        };
-        while !visited_addresses.contains(&pointer.next_instruction) {
-            visited_addresses.insert(pointer.next_instruction);
-            let instruction = &program[pointer.next_instruction as usize];
-            pointer = instruction.execute(&pointer);
-            if pointer.next_instruction as usize == program.len() {
-                // Found it!
-                return pointer.acc;
+        ["trace", "--json"] => {
+            println!("{:#}", Trace::record(&mut machine()).to_json())
+        }
+        ["coverage"] => {
+            let trace = Trace::record(&mut machine());
+            println!("{}", Coverage::from_trace(&program, &trace));
+        }
+        ["coverage", "--json"] => {
+            let trace = Trace::record(&mut machine());
+            println!("{:#}", Coverage::from_trace(&program, &trace).to_json());
+        }
+        ["debug"] => {
+            let mut debugger = Debugger::new(machine());
+            if let Err(error) = debugger.run(io::stdin().lock(), io::stdout()) {
+                eprintln!("{}", error);
+                process::exit(1);
             }
         }
-        // reset instruction
-        match program[index].code {
-            InstructionCode::Nop => program[index].code = InstructionCode::Jmp,
-            InstructionCode::Jmp => program[index].code = InstructionCode::Nop,
-            _ => {}
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
         }
     }
+}
 
-    0
+/// The accumulator when the program stops, or is about to repeat itself.
+fn part_1(program: &[Instruction]) -> i32 {
+    let mut machine = Machine::new(program.to_vec());
+    machine.run();
+    machine.acc()
 }
 
-fn parse_input(contents: &str) -> Vec<Instruction> {
-    contents
-        .split('\n')
-        .map(|l| l.parse::<Instruction>().unwrap())
-        .collect()
+fn part_2(program: &[Instruction]) -> Result<Repair, &'static str> {
+    repair(program)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_build_instruction() {
-        assert_eq!(
-            "acc +1".parse::<Instruction>().unwrap(),
-            Instruction {
-                code: InstructionCode::Acc,
-                val: 1
-            }
-        );
-
-        assert_eq!(
-            "nop -1".parse::<Instruction>().unwrap(),
-            Instruction {
-                code: InstructionCode::Nop,
-                val: -1
-            }
-        );
-
-        assert_eq!(
-            "jmp +4".parse::<Instruction>().unwrap(),
-            Instruction {
-                code: InstructionCode::Jmp,
-                val: 4
-            }
-        );
-    }
-
-    #[test]
-    fn test_sample_input_part_1() {
-        let sample_input = parse_input(
-            "nop +0
+    const SAMPLE_PROGRAM: &str = "nop +0
 acc +1
 jmp +4
 acc +3
@@ -176,26 +118,29 @@ jmp -3
 acc -99
 acc +1
 jmp -4
-acc +6",
-        );
+acc +6";
+
+    #[test]
+    fn test_sample_input_part_1() {
+        let sample_input = assemble(SAMPLE_PROGRAM).unwrap();
 
         assert_eq!(part_1(&sample_input), 5);
     }
 
     #[test]
     fn test_sample_input_part_2() {
-        let sample_input = parse_input(
-            "nop +0
-acc +1
-jmp +4
-acc +3
-jmp -3
-acc -99
-acc +1
-jmp -4
-acc +6",
-        );
+        let sample_input = assemble(SAMPLE_PROGRAM).unwrap();
+
+        assert_eq!(part_2(&sample_input).unwrap().acc, 8);
+    }
+
+    #[test]
+    fn test_part_1_runs_every_instruction() {
+        let program = assemble("set acc +5\nacc a\njmp +0").unwrap();
+        assert_eq!(part_1(&program), 5);
 
-        assert_eq!(part_2(&sample_input), 8);
+        // Stops at the overflow instead of panicking
+        let program = assemble("acc +2147483647\nacc +1\njmp +0").unwrap();
+        assert_eq!(part_1(&program), 2147483647);
     }
 }
@@ -1,9 +1,83 @@
-use aoc2020::read_string_input;
-use std::collections::VecDeque;
-use std::str::FromStr;
+use aoc2020::rng::Rng;
+use aoc2020::{option_value, read_string_input};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::hint::black_box;
+use std::io::{self, BufRead};
+use std::process;
+use std::time::{Duration, Instant};
 
 //
-// The idea here is to build up a vec with structs for the <preamble size> last numbers
+// A number is valid if it's the sum of two different entries among the
+// <preamble size> numbers before it. Duplicates count as different entries,
+// so with 25 in the window twice, 50 is valid; with 25 only once, it isn't.
+//
+// `XMAS` keeps the window in order, so it knows which number to drop next,
+// along with how many times each value is in it. Checking a number is then a
+// lookup per entry: for every `a` in the window, is `number - a` there too?
+// That makes adding a number O(1) and checking one O(preamble).
+//
+
+/// Something that can tell whether a number is valid given the ones before
+/// it, so `XMAS` and `Triangle` can be compared.
+trait Window {
+    fn with_preamble(preamble: usize) -> Self;
+    fn add_entry(&mut self, number: i64);
+    fn valid_number(&self, number: i64) -> bool;
+}
+
+#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
+struct XMAS {
+    preamble: usize,
+    window: VecDeque<i64>,
+    counts: HashMap<i64, usize>,
+}
+
+impl XMAS {
+    fn new(preamble: usize) -> XMAS {
+        XMAS {
+            preamble,
+            window: VecDeque::with_capacity(preamble + 1),
+            counts: HashMap::with_capacity(preamble + 1),
+        }
+    }
+}
+
+impl Window for XMAS {
+    fn with_preamble(preamble: usize) -> Self {
+        XMAS::new(preamble)
+    }
+
+    fn add_entry(&mut self, number: i64) {
+        if self.window.len() >= self.preamble {
+            if let Some(old) = self.window.pop_front() {
+                let count = self.counts.get_mut(&old).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&old);
+                }
+            }
+        }
+        self.window.push_back(number);
+        *self.counts.entry(number).or_insert(0) += 1;
+    }
+
+    fn valid_number(&self, number: i64) -> bool {
+        self.window.iter().any(|&a| match number.checked_sub(a) {
+            Some(b) => match self.counts.get(&b) {
+                Some(&count) => b != a || count >= 2,
+                None => false,
+            },
+            None => false,
+        })
+    }
+}
+
+//
+// The original implementation, kept to benchmark against. It builds up a vec
+// with structs for the <preamble size> last numbers
 //
 // Each of those structs should contain all numbers valid in the current data chunk because of this number.
 //
@@ -42,9 +116,10 @@ use std::str::FromStr;
 //
 //  Checking validity for a number can be done by iterating through the list and
 //  seeing if the new number is present in any of the `valid_numbers` lists.
+//  Both take O(preamble²) time, and the triangle takes as much memory.
 
 #[derive(Debug)]
-struct XMAS {
+struct Triangle {
     preamble: usize,
     data: VecDeque<Entry>,
 }
@@ -64,39 +139,27 @@ impl From<i64> for Entry {
     }
 }
 
-impl FromStr for Entry {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let number = s.parse::<i64>().unwrap();
-        Ok(Entry {
-            number,
-            valid_numbers: Vec::new(),
-        })
-    }
-}
-
-impl XMAS {
-    fn new(preamble: usize) -> XMAS {
-        XMAS {
+impl Window for Triangle {
+    fn with_preamble(preamble: usize) -> Self {
+        Triangle {
             preamble,
             data: VecDeque::new(),
         }
     }
-    /// Parse a &str token and add it to &self.data
-    fn add_entry(&mut self, new_entry: Entry) {
+
+    fn add_entry(&mut self, number: i64) {
         if self.data.len() >= self.preamble {
             self.data.pop_front();
         }
         for entry in self.data.iter_mut() {
-            entry.valid_numbers.push(entry.number + new_entry.number);
+            entry.valid_numbers.push(entry.number + number);
         }
-        self.data.push_back(new_entry);
+        self.data.push_back(Entry::from(number));
     }
 
-    fn valid_number(&self, entry: &Entry) -> bool {
+    fn valid_number(&self, number: i64) -> bool {
         for e in &self.data {
-            if e.valid_numbers.contains(&entry.number) {
+            if e.valid_numbers.contains(&number) {
                 return true;
             }
         }
@@ -104,35 +167,411 @@ impl XMAS {
     }
 }
 
-fn main() {
-    let input = read_string_input("in-data/day9.txt");
+/// Check every number after the preamble, returning how many were invalid
+/// and how long it took.
+fn time_checks<W: Window>(numbers: &[i64], preamble: usize) -> (usize, Duration) {
+    let start = Instant::now();
+    let mut window = W::with_preamble(preamble);
+    let mut invalid = 0;
+    for (index, &number) in numbers.iter().enumerate() {
+        if index >= preamble && !black_box(&window).valid_number(number) {
+            invalid += 1;
+        }
+        window.add_entry(number);
+    }
+    (invalid, start.elapsed())
+}
 
-    println!("Part 1: {}", part_1(&input, 25));
-    println!("Part 2: {}", part_2(&input, 25));
+fn bench(numbers: &[i64]) {
+    println!("preamble  triangle      counts");
+    for &preamble in &[5, 25, 100, 250] {
+        let (expected, triangle) = time_checks::<Triangle>(numbers, preamble);
+        let (invalid, counts) = time_checks::<XMAS>(numbers, preamble);
+        assert_eq!(invalid, expected);
+        println!(
+            "{:>8}  {:>8.2}ms  {:>8.2}ms",
+            preamble,
+            triangle.as_secs_f64() * 1000.0,
+            counts.as_secs_f64() * 1000.0
+        );
+    }
 }
 
-fn part_1(input: &str, preamble_size: usize) -> i64 {
-    let mut xmas = XMAS::new(preamble_size);
-    let data = input.split_ascii_whitespace().collect::<Vec<&str>>();
+/// A number that isn't the sum of two of the numbers before it, where in
+/// the stream it was found (counting from 0), and the numbers it was checked
+/// against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InvalidNumber {
+    index: usize,
+    number: i64,
+    window: Vec<i64>,
+}
 
-    let preamble = data.iter().take(preamble_size);
-    for token in preamble {
-        if let Ok(entry) = token.parse::<Entry>() {
-            xmas.add_entry(entry);
-        }
+impl fmt::Display for InvalidNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let window: Vec<String> = self.window.iter().map(i64::to_string).collect();
+        write!(
+            f,
+            "{}: {} is not the sum of two of [{}]",
+            self.index,
+            self.number,
+            window.join(", ")
+        )
     }
+}
 
-    for token in data.iter().skip(preamble_size) {
-        if let Ok(number) = token.parse::<i64>() {
-            let entry = Entry::from(number);
-            if xmas.valid_number(&entry) {
-                xmas.add_entry(entry);
+/// Wraps a stream of numbers, yielding the invalid ones as they arrive.
+///
+/// Only the last `preamble` numbers are kept, so the stream can be as long
+/// as it likes, or never end.
+struct InvalidNumbers<I> {
+    numbers: I,
+    xmas: XMAS,
+    index: usize,
+}
+
+impl<I: Iterator<Item = i64>> Iterator for InvalidNumbers<I> {
+    type Item = InvalidNumber;
+
+    fn next(&mut self) -> Option<InvalidNumber> {
+        for number in &mut self.numbers {
+            let index = self.index;
+            self.index += 1;
+            // Invalid numbers still count towards the window for the ones
+            // after them
+            let valid = index < self.xmas.preamble || self.xmas.valid_number(number);
+            let window = if valid {
+                vec![]
             } else {
-                return number;
+                self.xmas.window.iter().copied().collect()
+            };
+            self.xmas.add_entry(number);
+            if !valid {
+                return Some(InvalidNumber {
+                    index,
+                    number,
+                    window,
+                });
+            }
+        }
+        None
+    }
+}
+
+fn invalid_numbers<I: IntoIterator<Item = i64>>(
+    numbers: I,
+    preamble: usize,
+) -> InvalidNumbers<I::IntoIter> {
+    InvalidNumbers {
+        numbers: numbers.into_iter(),
+        xmas: XMAS::new(preamble),
+        index: 0,
+    }
+}
+
+/// The numbers in `reader`, read a line at a time. Anything that isn't a
+/// number is skipped, and reading stops at the first I/O error.
+fn read_numbers<R: BufRead>(reader: R) -> impl Iterator<Item = i64> {
+    reader.lines().map_while(Result::ok).flat_map(|line| {
+        line.split_ascii_whitespace()
+            .filter_map(|token| token.parse().ok())
+            .collect::<Vec<i64>>()
+    })
+}
+
+/// Generated numbers are between `-BOUND` and `BOUND`, apart from the range
+/// adding up to the weakness, which has to reach whatever target it's given.
+const BOUND: i64 = 1_000_000;
+
+/// Settings for generating an XMAS stream: every number is valid for the
+/// preamble size, apart from the ones deliberately planted.
+///
+/// Any stream of valid positive numbers grows exponentially, like the puzzle
+/// input does, so to stay within `BOUND` the window always holds `0`, `c`
+/// and `-c` for some `c`. Whenever the last copy of one of those is about to
+/// drop out, it's added again (`c + 0`, `-c + 0` or `c + -c`); otherwise the
+/// next number is the sum of two entries closest to a random target.
+#[derive(Debug, Clone)]
+struct Encoder {
+    preamble: usize,
+    length: usize,
+    seed: u64,
+    /// How many invalid numbers to plant at random after the preamble.
+    invalid: usize,
+    /// Plant this as the first invalid number, right after the preamble,
+    /// and start the stream with a range of at least three numbers adding up
+    /// to it. Needs a preamble of at least six.
+    weakness: Option<i64>,
+}
+
+/// A generated stream, and what was planted in it.
+#[derive(Debug, Clone)]
+struct Cipher {
+    numbers: Vec<i64>,
+    /// The indices of every invalid number, in order.
+    invalid: Vec<usize>,
+    /// The planted range summing to the weakness target, inclusive.
+    range: Option<(usize, usize)>,
+}
+
+impl Encoder {
+    /// Why these settings can't generate a stream, if they can't.
+    fn check(&self) -> Result<(), String> {
+        if self.preamble < 3 {
+            return Err(String::from("The preamble needs at least three numbers"));
+        }
+        if let Some(target) = self.weakness {
+            if self.preamble < 6 {
+                return Err(String::from("A weakness needs a preamble of at least six"));
+            }
+            if self.length <= self.preamble {
+                return Err(String::from(
+                    "A weakness needs a stream longer than the preamble",
+                ));
+            }
+            // The rest of the range is within BOUND, and the last number of
+            // it makes up the difference
+            let limit = i64::MAX - (self.preamble as i64).saturating_mul(BOUND);
+            if target.checked_abs().is_none_or(|target| target > limit) {
+                return Err(format!(
+                    "With a preamble of {}, the weakness has to be between -{} and {}",
+                    self.preamble, limit, limit
+                ));
+            }
+            // The window always holds c and -c
+            if target == 0 {
+                return Err(String::from(
+                    "The weakness can't be 0: two numbers in the preamble always add up to it",
+                ));
+            }
+        }
+        let first = self.preamble + self.weakness.map_or(0, |_| 1);
+        if self.invalid > self.length.saturating_sub(first) {
+            return Err(format!(
+                "Not enough room for {} invalid numbers",
+                self.invalid
+            ));
+        }
+        Ok(())
+    }
+
+    fn encode(&self) -> Result<Cipher, String> {
+        self.check()?;
+        let mut rng = Rng::new(self.seed);
+        let (mut numbers, range, c) = self.preamble_numbers(&mut rng);
+        let anchors = [0, c, -c];
+
+        let first = self.preamble + self.weakness.map_or(0, |_| 1);
+        let mut to_plant = self.invalid;
+        let mut invalid = vec![];
+
+        let mut xmas = window_of(&numbers);
+        for index in self.preamble..self.length {
+            let front = xmas.window[0];
+            let keep_alive = anchors.contains(&front) && xmas.counts[&front] == 1;
+            // Pick the remaining positions uniformly from what's left, but
+            // put off planting while an anchor needs adding again, unless
+            // there's no room left to
+            let left = self.length - index;
+            let plant = index >= first
+                && to_plant > 0
+                && (to_plant == left || (!keep_alive && rng.index(left) < to_plant));
+
+            let number = match self.weakness {
+                Some(target) if index == self.preamble => {
+                    invalid.push(index);
+                    target
+                }
+                _ if plant => {
+                    to_plant -= 1;
+                    invalid.push(index);
+                    loop {
+                        let number = rng.range(-BOUND, BOUND);
+                        if !xmas.valid_number(number) {
+                            break number;
+                        }
+                    }
+                }
+                _ if keep_alive => front,
+                _ => valid_sum(&mut rng, &xmas.window),
+            };
+            xmas.add_entry(number);
+            numbers.push(number);
+        }
+
+        Ok(Cipher {
+            numbers,
+            invalid,
+            range,
+        })
+    }
+
+    /// The preamble, the range adding up to the weakness if there is one,
+    /// and `c`. The preamble ends with the anchors `0 c -c`.
+    fn preamble_numbers(&self, rng: &mut Rng) -> (Vec<i64>, Option<(usize, usize)>, i64) {
+        loop {
+            let c = rng.range(1, BOUND);
+            let mut numbers = vec![];
+            let mut range = None;
+            if let Some(target) = self.weakness {
+                let len = 3 + rng.index(self.preamble - 5);
+                numbers = (1..len).map(|_| rng.range(-BOUND, BOUND)).collect();
+                numbers.push(target - numbers.iter().sum::<i64>());
+                range = Some((0, len - 1));
             }
+            while numbers.len() < self.preamble - 3 {
+                numbers.push(rng.range(-BOUND, BOUND));
+            }
+            numbers.extend(&[0, c, -c]);
+
+            // Two numbers in the preamble adding up to the weakness would
+            // make it valid, so try again until none do
+            match self.weakness {
+                Some(target) if window_of(&numbers).valid_number(target) => continue,
+                _ => return (numbers, range, c),
+            }
+        }
+    }
+}
+
+fn window_of(numbers: &[i64]) -> XMAS {
+    let mut xmas = XMAS::new(numbers.len());
+    for &number in numbers {
+        xmas.add_entry(number);
+    }
+    xmas
+}
+
+/// The sum of two different entries in the window closest to a random
+/// target, out of those within `BOUND`. There's always one, since `0` is in
+/// the window.
+fn valid_sum(rng: &mut Rng, window: &VecDeque<i64>) -> i64 {
+    let target = rng.range(-BOUND, BOUND);
+    let mut best = None;
+    for (i, a) in window.iter().enumerate() {
+        for b in window.iter().skip(i + 1) {
+            // Only the weakness range can be big enough to overflow
+            let sum = a.saturating_add(*b);
+            if (-BOUND..=BOUND).contains(&sum)
+                && best.is_none_or(|best: i64| (sum - target).abs() < (best - target).abs())
+            {
+                best = Some(sum);
+            }
+        }
+    }
+    best.unwrap()
+}
+
+const USAGE: &str = "Usage: day9 [--preamble <n>] [--stdin | invalid | bench | generate \
+[--length <n>] [--seed <n>] [--invalid <n>] [--weakness <n>]]";
+
+fn main() {
+    // Options:
+    //   --preamble <n>  how many numbers each number is checked against
+    //                   (default 25)
+    //   --stdin         check numbers from stdin instead of solving the
+    //                   puzzle, printing each invalid one as soon as it's read
+    // Commands:
+    //   invalid         every invalid number in the puzzle input, with the
+    //                   numbers it was checked against
+    //   bench           time checking the puzzle input with different
+    //                   preamble sizes, against the original implementation
+    //   generate        print a valid stream of --length numbers (default
+    //                   1000) from --seed, planting --invalid numbers and a
+    //                   range adding up to the first invalid one, --weakness.
+    //                   Where they went is printed to stderr.
+    let mut preamble = 25;
+    let mut encoder = Encoder {
+        preamble,
+        length: 1000,
+        seed: 0,
+        invalid: 0,
+        weakness: None,
+    };
+    let mut stdin = false;
+    let mut command = None;
+    let mut options = env::args().skip(1);
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--preamble" => preamble = option_value(options.next(), USAGE),
+            "--length" => encoder.length = option_value(options.next(), USAGE),
+            "--seed" => encoder.seed = option_value(options.next(), USAGE),
+            "--invalid" => encoder.invalid = option_value(options.next(), USAGE),
+            "--weakness" => encoder.weakness = Some(option_value(options.next(), USAGE)),
+            "--stdin" => stdin = true,
+            "invalid" | "bench" | "generate" if command.is_none() => command = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(1);
+            }
+        }
+    }
+
+    if command.as_deref() == Some("generate") {
+        encoder.preamble = preamble;
+        let cipher = match encoder.encode() {
+            Ok(cipher) => cipher,
+            Err(message) => {
+                eprintln!("{}", message);
+                process::exit(1);
+            }
+        };
+        for number in &cipher.numbers {
+            println!("{}", number);
+        }
+        // What was planted goes to stderr, so stdout can be piped straight
+        // back in with --stdin
+        eprintln!("Invalid numbers at: {:?}", cipher.invalid);
+        if let Some((start, end)) = cipher.range {
+            eprintln!("Range adding up to the weakness: {} to {}", start, end);
+        }
+        return;
+    }
+
+    if stdin {
+        for invalid in invalid_numbers(read_numbers(io::stdin().lock()), preamble) {
+            println!("{}", invalid);
+        }
+        return;
+    }
+
+    let input = read_string_input("in-data/day9.txt");
+    match command.as_deref() {
+        Some("invalid") => {
+            for invalid in all_invalid(&input, preamble) {
+                println!("{}", invalid);
+            }
+            return;
+        }
+        Some("bench") => {
+            let numbers: Vec<i64> = read_numbers(input.as_bytes()).collect();
+            bench(&numbers);
+            return;
         }
+        _ => {}
     }
-    0
+
+    match part_1(&input, preamble) {
+        Some(number) => println!("Part 1: {}", number),
+        None => println!("Part 1: every number is valid"),
+    }
+    match part_2(&input, preamble) {
+        Ok(weakness) => println!("Part 2: {}", weakness),
+        Err(message) => println!("Part 2: {}", message),
+    }
+}
+
+/// The first invalid number, if there is one.
+fn part_1(input: &str, preamble_size: usize) -> Option<i64> {
+    invalid_numbers(read_numbers(input.as_bytes()), preamble_size)
+        .next()
+        .map(|invalid| invalid.number)
+}
+
+/// Every invalid number, for finding all the anomalies in a stream rather
+/// than just the first.
+fn all_invalid(input: &str, preamble_size: usize) -> Vec<InvalidNumber> {
+    invalid_numbers(read_numbers(input.as_bytes()), preamble_size).collect()
 }
 
 // --- Part Two ---
@@ -167,37 +606,66 @@ fn part_1(input: &str, preamble_size: usize) -> i64 {
 // To find the encryption weakness, add together the smallest and largest number in this contiguous range; in this example, these are 15 and 47, producing 62.
 
 // What is the encryption weakness in your XMAS-encrypted list of numbers?
-fn part_2(input: &str, preamble_size: usize) -> i64 {
-    let invalid_number = part_1(input, preamble_size);
-
-    let mut data: Vec<i64> = vec![];
-    for token in input.split_ascii_whitespace() {
-        data.push(token.parse::<i64>().unwrap());
-    }
-    // let data = input.split_ascii_whitespace().map(|s| s.parse().unwrap()).collect::<Vec<i64>>();
-
-    let mut range: Vec<&i64> = vec![];
-    for (index, item) in data.iter().enumerate() {
-        range = vec![item];
-        let mut sum = *item;
-        for other in data.iter().skip(index + 1) {
-            if sum + other > invalid_number {
-                break;
-            }
-            range.push(other);
-            sum += other;
-        }
-        if sum == invalid_number {
-            break;
-        }
-    }
 
-    range.sort();
+/// A contiguous range of at least two numbers, from `start` to `end`
+/// inclusive, that adds up to the first invalid number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Weakness {
+    start: usize,
+    end: usize,
+    /// The smallest and largest number in the range, added together.
+    weakness: i64,
+}
 
-    let smallest = range.iter().min().unwrap();
-    let largest = range.iter().max().unwrap();
+impl fmt::Display for Weakness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (numbers {} to {})",
+            self.weakness, self.start, self.end
+        )
+    }
+}
 
-    *smallest + *largest
+/// The first contiguous range of at least two numbers summing to `target`,
+/// as inclusive indices.
+///
+/// A range `i..=j` sums to `prefix[j + 1] - prefix[i]`, so for each end `j`
+/// this looks up whether `prefix[j + 1] - target` was an earlier prefix sum.
+/// Unlike growing and shrinking a window, that also works with negative
+/// numbers.
+fn find_range(numbers: &[i64], target: i64) -> Option<(usize, usize)> {
+    // Prefix sums can overflow i64 on long inputs, but not i128
+    let mut starts: HashMap<i128, usize> = HashMap::new();
+    let mut before = 0;
+    let mut prefix = numbers.first().copied().unwrap_or(0) as i128;
+    for (end, &number) in numbers.iter().enumerate().skip(1) {
+        // The range must have at least two numbers, so it can start no
+        // later than end - 1
+        starts.entry(before).or_insert(end - 1);
+        before = prefix;
+        prefix += number as i128;
+        if let Some(&start) = starts.get(&(prefix - target as i128)) {
+            return Some((start, end));
+        }
+    }
+    None
+}
+
+fn part_2(input: &str, preamble_size: usize) -> Result<Weakness, String> {
+    let numbers: Vec<i64> = read_numbers(input.as_bytes()).collect();
+    let invalid = invalid_numbers(numbers.iter().copied(), preamble_size)
+        .next()
+        .ok_or("Every number is valid")?;
+
+    let (start, end) = find_range(&numbers, invalid.number)
+        .ok_or(format!("No contiguous range adds up to {}", invalid.number))?;
+    let range = &numbers[start..=end];
+    Ok(Weakness {
+        start,
+        end,
+        weakness: range.iter().min().unwrap() + range.iter().max().unwrap(),
+    })
 }
 
 #[cfg(test)]
@@ -239,14 +707,13 @@ mod tests {
 25";
         let mut xmas = XMAS::new(25);
         for token in sample_input.split_ascii_whitespace() {
-            let entry = token.parse::<Entry>().unwrap();
-            xmas.add_entry(entry);
+            xmas.add_entry(token.parse().unwrap());
         }
 
-        assert!(xmas.valid_number(&Entry::from(26)));
-        assert!(xmas.valid_number(&Entry::from(49)));
-        assert!(!xmas.valid_number(&Entry::from(100)));
-        assert!(!xmas.valid_number(&Entry::from(50)));
+        assert!(xmas.valid_number(26));
+        assert!(xmas.valid_number(49));
+        assert!(!xmas.valid_number(100));
+        assert!(!xmas.valid_number(50));
     }
 
     #[test]
@@ -286,17 +753,15 @@ mod tests {
 25";
         let mut xmas = XMAS::new(25);
         for token in sample_input.split_ascii_whitespace() {
-            let entry = token.parse::<Entry>().unwrap();
-            xmas.add_entry(entry);
+            xmas.add_entry(token.parse().unwrap());
         }
 
-        xmas.add_entry(Entry::from(45));
+        xmas.add_entry(45);
 
-        dbg!(&xmas);
-        assert!(xmas.valid_number(&Entry::from(26)));
-        assert!(!xmas.valid_number(&Entry::from(65)));
-        assert!(xmas.valid_number(&Entry::from(64)));
-        assert!(xmas.valid_number(&Entry::from(66)));
+        assert!(xmas.valid_number(26));
+        assert!(!xmas.valid_number(65));
+        assert!(xmas.valid_number(64));
+        assert!(xmas.valid_number(66));
     }
 
     #[test]
@@ -322,11 +787,227 @@ mod tests {
 309
 576";
 
-        assert_eq!(part_1(&sample_data, 5), 127);
-        // let sample_data = sample_data.split_ascii_whitespace();
-        // let mut data = read_preamble(&sample_data, 5);
+        assert_eq!(part_1(sample_data, 5), Some(127));
+        assert_eq!(
+            part_2(sample_data, 5),
+            Ok(Weakness {
+                start: 2,
+                end: 5,
+                weakness: 62
+            })
+        );
+    }
+
+    #[test]
+    fn test_find_range() {
+        assert_eq!(find_range(&[1, 2, 3, 4], 7), Some((2, 3)));
+        // At least two numbers, even when one alone is the target
+        assert_eq!(find_range(&[7, 1, 6], 7), Some((1, 2)));
+        assert_eq!(find_range(&[7, 0], 7), Some((0, 1)));
+        // Negative numbers, where shrinking a window from the left when the
+        // sum is too big would skip the answer
+        assert_eq!(find_range(&[5, 10, -8, 3, 1], 5), Some((1, 3)));
+        assert_eq!(find_range(&[-3, -4, 2], -7), Some((0, 1)));
+        assert_eq!(find_range(&[1, 2, 3], 100), None);
+        assert_eq!(find_range(&[5], 5), None);
+        assert_eq!(find_range(&[], 0), None);
+    }
+
+    #[test]
+    fn test_part_2_without_answer() {
+        // 1 + 2 = 3 is fine, 10 is invalid but no run of numbers adds up to it
+        assert_eq!(
+            part_2("1 2 3 10", 2),
+            Err(String::from("No contiguous range adds up to 10"))
+        );
+        assert!(part_2("1 2 3 5 8", 2).is_err());
+    }
+
+    #[test]
+    fn test_duplicates_in_window() {
+        let mut xmas = XMAS::new(3);
+        for number in &[25, 10, 25] {
+            xmas.add_entry(*number);
+        }
+        assert!(xmas.valid_number(50));
+        assert!(xmas.valid_number(35));
+        assert!(!xmas.valid_number(20));
+
+        // The first 25 drops out
+        xmas.add_entry(1);
+        assert!(!xmas.valid_number(50));
+        assert!(xmas.valid_number(26));
+    }
+
+    #[test]
+    fn test_same_as_triangle() {
+        // Lots of duplicates, and a mix of valid and invalid numbers
+        let numbers: Vec<i64> = (0..1000).map(|i: i64| i * i * 7919 % 100).collect();
+
+        for &preamble in &[2, 5, 25] {
+            assert_eq!(
+                time_checks::<XMAS>(&numbers, preamble).0,
+                time_checks::<Triangle>(&numbers, preamble).0
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_numbers_stream() {
+        // 1 + 2 = 3, but after that no number is the sum of the two before it.
+        // The stream never ends, so this only works if it's checked lazily.
+        let invalid: Vec<(usize, i64)> = invalid_numbers(1.., 2)
+            .take(3)
+            .map(|invalid| (invalid.index, invalid.number))
+            .collect();
+
+        assert_eq!(invalid, vec![(3, 4), (4, 5), (5, 6)]);
+    }
+
+    #[test]
+    fn test_all_invalid() {
+        let invalid = all_invalid("1 2 3 10 13 5 18", 2);
+
+        assert_eq!(
+            invalid,
+            vec![
+                InvalidNumber {
+                    index: 3,
+                    number: 10,
+                    window: vec![2, 3]
+                },
+                InvalidNumber {
+                    index: 5,
+                    number: 5,
+                    window: vec![10, 13]
+                },
+            ]
+        );
+        assert_eq!(
+            invalid[0].to_string(),
+            "3: 10 is not the sum of two of [2, 3]"
+        );
+        assert_eq!(part_1("1 2 3 5 8", 2), None);
+        assert!(all_invalid("1 2 3 5 8", 2).is_empty());
+    }
+
+    #[test]
+    fn test_encoder_valid_stream() {
+        let encoder = Encoder {
+            preamble: 25,
+            length: 5000,
+            seed: 1,
+            invalid: 0,
+            weakness: None,
+        };
+        let cipher = encoder.encode().unwrap();
+
+        assert_eq!(cipher.numbers.len(), 5000);
+        assert_eq!(
+            invalid_numbers(cipher.numbers.iter().copied(), 25).next(),
+            None
+        );
+        // Doesn't blow up
+        assert!(cipher.numbers.iter().all(|n| n.abs() <= BOUND));
+        assert_eq!(encoder.encode().unwrap().numbers, cipher.numbers);
+    }
+
+    #[test]
+    fn test_encoder_planted() {
+        for seed in 0..20 {
+            let preamble = 6 + seed as usize % 10;
+            let cipher = Encoder {
+                preamble,
+                length: 300,
+                seed,
+                invalid: 5,
+                weakness: Some(1000 + seed as i64),
+            }
+            .encode()
+            .unwrap();
+            let found: Vec<usize> = invalid_numbers(cipher.numbers.iter().copied(), preamble)
+                .map(|invalid| invalid.index)
+                .collect();
+            assert_eq!(found, cipher.invalid, "seed {}", seed);
+            assert_eq!(found.len(), 6);
+
+            let (start, end) = cipher.range.unwrap();
+            assert_eq!(
+                cipher.numbers[start..=end].iter().sum::<i64>(),
+                1000 + seed as i64
+            );
+            // The weakness finder may find another range, but it has to add
+            // up too
+            let (start, end) = find_range(&cipher.numbers, 1000 + seed as i64).unwrap();
+            assert!(end > start);
+            assert_eq!(
+                cipher.numbers[start..=end].iter().sum::<i64>(),
+                1000 + seed as i64
+            );
+        }
+    }
+
+    #[test]
+    fn test_encoder_settings() {
+        let encoder = Encoder {
+            preamble: 6,
+            length: 100,
+            seed: 0,
+            invalid: 0,
+            weakness: Some(0),
+        };
+        assert!(encoder.encode().is_err());
+
+        let small = Encoder {
+            preamble: 2,
+            weakness: None,
+            ..encoder.clone()
+        };
+        assert!(small.encode().is_err());
+        let small = Encoder {
+            preamble: 5,
+            weakness: Some(10),
+            ..encoder.clone()
+        };
+        assert!(small.encode().is_err());
+        let huge = Encoder {
+            preamble: 10,
+            weakness: Some(i64::MIN),
+            ..encoder.clone()
+        };
+        assert!(huge.encode().is_err());
+        let huge = Encoder {
+            preamble: 10,
+            weakness: Some(i64::MAX - 9 * BOUND),
+            ..encoder.clone()
+        };
+        assert!(huge.encode().is_err());
+        let largest = Encoder {
+            preamble: 10,
+            weakness: Some(-(i64::MAX - 10 * BOUND)),
+            ..encoder.clone()
+        };
+        let cipher = largest.encode().unwrap();
+        let (start, end) = cipher.range.unwrap();
+        assert_eq!(
+            cipher.numbers[start..=end].iter().sum::<i64>(),
+            -(i64::MAX - 10 * BOUND)
+        );
+        let crowded = Encoder {
+            invalid: 95,
+            weakness: Some(10),
+            ..encoder
+        };
+        assert!(crowded.encode().is_err());
+    }
+
+    #[test]
+    fn test_read_numbers() {
+        let input = "35\n20 15\n\nnot a number\n25\n";
 
-        // Do something here...
-        // How to continue processing input?
+        assert_eq!(
+            read_numbers(input.as_bytes()).collect::<Vec<i64>>(),
+            vec![35, 20, 15, 25]
+        );
     }
 }
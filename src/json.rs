@@ -0,0 +1,374 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A minimal JSON value, enough to export puzzle data and read it back.
+///
+/// Numbers are integers only, since that is all the puzzles deal in. Objects
+/// keep their keys in insertion order. `{}` formats compactly and `{:#}`
+/// pretty prints with two space indentation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Look up a key in an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: Option<usize>) -> fmt::Result {
+        // Separator before each item, and before the closing bracket
+        let newline = |f: &mut fmt::Formatter<'_>, depth: usize| match indent {
+            Some(_) => write!(f, "\n{}", "  ".repeat(depth)),
+            None => Ok(()),
+        };
+        let depth = indent.unwrap_or(0);
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) if items.is_empty() => write!(f, "[]"),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, depth + 1)?;
+                    item.write(f, indent.map(|d| d + 1))?;
+                }
+                newline(f, depth)?;
+                write!(f, "]")
+            }
+            Json::Object(entries) if entries.is_empty() => write!(f, "{{}}"),
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, depth + 1)?;
+                    write_string(f, key)?;
+                    write!(f, ":{}", if indent.is_some() { " " } else { "" })?;
+                    value.write(f, indent.map(|d| d + 1))?;
+                }
+                newline(f, depth)?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, if f.alternate() { Some(0) } else { None })
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Self {
+        Json::Number(n)
+    }
+}
+
+impl FromStr for Json {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("Trailing characters"));
+        }
+        Ok(value)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        format!("{} at character {}", message, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected {:?}", c)))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        let end = self.pos + word.len();
+        if end <= self.chars.len() && self.chars[self.pos..end].iter().copied().eq(word.chars()) {
+            self.pos = end;
+            Ok(value)
+        } else {
+            Err(self.error("Invalid literal"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some('n') => self.literal("null", Json::Null),
+            Some('t') => self.literal("true", Json::Bool(true)),
+            Some('f') => self.literal("false", Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("Expected a value")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        if self.chars[self.pos] == '-' {
+            self.pos += 1;
+        }
+        while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_digit() {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| self.error("Invalid integer"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let c = *self
+                .chars
+                .get(self.pos)
+                .ok_or_else(|| self.error("Unterminated string"))?;
+            self.pos += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let escaped = *self
+                        .chars
+                        .get(self.pos)
+                        .ok_or_else(|| self.error("Unterminated string"))?;
+                    self.pos += 1;
+                    s.push(match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let end = self.pos + 4;
+                            let hex: String = self
+                                .chars
+                                .get(self.pos..end)
+                                .ok_or_else(|| self.error("Invalid escape"))?
+                                .iter()
+                                .collect();
+                            self.pos = end;
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(std::char::from_u32)
+                                .ok_or_else(|| self.error("Invalid escape"))?
+                        }
+                        _ => return Err(self.error("Invalid escape")),
+                    });
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = vec![];
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut entries = vec![];
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(entries));
+                }
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let value = Json::Object(vec![
+            (String::from("name"), Json::from("say \"hi\"")),
+            (
+                String::from("list"),
+                Json::Array(vec![Json::from(1), Json::from(-2), Json::Null]),
+            ),
+            (String::from("empty"), Json::Array(vec![])),
+        ]);
+
+        assert_eq!(
+            value.to_string(),
+            r#"{"name":"say \"hi\"","list":[1,-2,null],"empty":[]}"#
+        );
+        assert_eq!(
+            format!("{:#}", value),
+            r#"{
+  "name": "say \"hi\"",
+  "list": [
+    1,
+    -2,
+    null
+  ],
+  "empty": []
+}"#
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let value: Json = r#" { "a" : [ true, false, null, 12, "x\nA" ], "b": {} } "#
+            .parse()
+            .unwrap();
+
+        assert_eq!(value.get("b"), Some(&Json::Object(vec![])));
+        assert_eq!(
+            value.get("a").unwrap().as_array().unwrap(),
+            &[
+                Json::Bool(true),
+                Json::Bool(false),
+                Json::Null,
+                Json::Number(12),
+                Json::from("x\nA"),
+            ]
+        );
+        assert_eq!(value.to_string().parse::<Json>(), Ok(value.clone()));
+        assert_eq!(format!("{:#}", value).parse::<Json>(), Ok(value));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("".parse::<Json>().is_err());
+        assert!("[1, 2".parse::<Json>().is_err());
+        assert!("{\"a\" 1}".parse::<Json>().is_err());
+        assert!("1.5".parse::<Json>().is_err());
+        assert!("\"abc".parse::<Json>().is_err());
+        assert!("nul".parse::<Json>().is_err());
+    }
+}
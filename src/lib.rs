@@ -1,4 +1,24 @@
+pub mod automaton;
+pub mod bigint;
+pub mod json;
+pub mod rng;
+pub mod vm;
+
 use std::fs;
+use std::process;
+use std::str::FromStr;
+
+/// The value following a command line option, parsed as a `T`. If it's
+/// missing or doesn't parse, prints `usage` and exits.
+pub fn option_value<T: FromStr>(value: Option<String>, usage: &str) -> T {
+    match value.map(|v| v.parse()) {
+        Some(Ok(value)) => value,
+        _ => {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    }
+}
 
 pub fn read_string_input(filename: &str) -> String {
     match fs::read_to_string(filename) {
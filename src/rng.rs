@@ -0,0 +1,81 @@
+/// A small, seedable pseudo-random number generator (SplitMix64), for
+/// generating test inputs that are the same on every run.
+///
+/// Not suitable for anything that needs unpredictable numbers.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, each equally likely. `n` must not be zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "Empty range");
+        // Reject the top partial copy of 0..n so every value has the same
+        // number of u64s mapping to it
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+
+    /// A number in `low..=high`, each equally likely.
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        assert!(low <= high, "Empty range");
+        let span = high.wrapping_sub(low) as u64;
+        let offset = match span.checked_add(1) {
+            Some(n) => self.below(n),
+            None => self.next_u64(),
+        };
+        low.wrapping_add(offset as i64)
+    }
+
+    pub fn index(&mut self, len: usize) -> usize {
+        self.below(len as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_numbers() {
+        let first: Vec<u64> = (0..5).map(|_| Rng::new(42).next_u64()).collect();
+        assert!(first.iter().all(|&x| x == first[0]));
+
+        let mut a = Rng::new(1);
+        let mut b = Rng::new(2);
+        assert_ne!(a.next_u64(), b.next_u64());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut rng = Rng::new(7);
+        let mut seen = [false; 7];
+        for _ in 0..1000 {
+            let x = rng.range(-3, 3);
+            assert!((-3..=3).contains(&x));
+            seen[(x + 3) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+
+        assert_eq!(rng.range(5, 5), 5);
+        rng.range(i64::MIN, i64::MAX);
+    }
+}
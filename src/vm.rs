@@ -0,0 +1,527 @@
+//! The handheld game console from day 8: a tiny machine running a program of
+//! instructions such as `acc +1` and `jmp -3`.
+//!
+//! The instructions come from a table (see `isa`), so the console can be
+//! extended with registers, conditional jumps and I/O without changing the
+//! parser or the `Machine`.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+pub mod analysis;
+pub mod asm;
+pub mod debugger;
+pub mod isa;
+pub mod repair;
+pub mod trace;
+
+use isa::{Action, Control, InstructionSet, Opcode, OperandKind};
+
+/// The number of registers: `acc` followed by `a` to `z`.
+pub const REGISTERS: usize = 27;
+
+/// The register day 8's `acc` instruction adds to.
+pub const ACCUMULATOR: usize = 0;
+
+pub fn register_name(register: usize) -> String {
+    match register {
+        ACCUMULATOR => String::from("acc"),
+        r => ((b'a' + r as u8 - 1) as char).to_string(),
+    }
+}
+
+fn parse_register(s: &str) -> Option<usize> {
+    match s.as_bytes() {
+        b"acc" => Some(ACCUMULATOR),
+        [c @ b'a'..=b'z'] => Some((c - b'a') as usize + 1),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Value(i32),
+    Register(usize),
+}
+
+impl Operand {
+    /// The literal value; only meaningful for operands the instruction set
+    /// guarantees are literals, like offsets.
+    pub fn value(&self) -> i32 {
+        match self {
+            Operand::Value(val) => *val,
+            Operand::Register(_) => 0,
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(register) = parse_register(s) {
+            return Ok(Operand::Register(register));
+        }
+        s.parse::<i32>()
+            .map(Operand::Value)
+            .map_err(|_| format!("Invalid operand {:?}", s))
+    }
+}
+
+/// Literals are always signed, as in the puzzle input.
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Value(val) => write!(f, "{:+}", val),
+            Operand::Register(register) => write!(f, "{}", register_name(*register)),
+        }
+    }
+}
+
+/// The state instructions act on: the registers and the I/O streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cpu {
+    pub registers: [i32; REGISTERS],
+    pub input: VecDeque<i32>,
+    pub output: Vec<i32>,
+}
+
+impl Cpu {
+    fn new() -> Cpu {
+        Cpu {
+            registers: [0; REGISTERS],
+            input: VecDeque::new(),
+            output: vec![],
+        }
+    }
+
+    pub fn read(&self, operand: Operand) -> i32 {
+        match operand {
+            Operand::Value(val) => val,
+            Operand::Register(register) => self.registers[register],
+        }
+    }
+
+    /// Store a value in a register operand. Writing to a literal does
+    /// nothing; the instruction set doesn't allow it.
+    pub fn write(&mut self, operand: Operand, value: i32) {
+        if let Operand::Register(register) = operand {
+            self.registers[register] = value;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: Opcode,
+    pub args: Vec<Operand>,
+}
+
+impl Instruction {
+    pub fn new(code: Opcode, args: Vec<Operand>) -> Instruction {
+        Instruction { code, args }
+    }
+
+    /// The value of the `Offset` operand, for opcodes that have one.
+    pub fn offset(&self) -> Option<i32> {
+        self.code
+            .operands
+            .iter()
+            .position(|kind| *kind == OperandKind::Offset)
+            .map(|i| self.args[i].value())
+    }
+
+    /// Where execution can go after this instruction, if it's at `address`.
+    pub fn successors(&self, address: usize) -> Vec<i64> {
+        let next = address as i64 + 1;
+        let target = address as i64 + self.offset().unwrap_or(1) as i64;
+        match self.code.control {
+            Control::Next => vec![next],
+            Control::Jump => vec![target],
+            Control::Branch if target == next => vec![next],
+            Control::Branch => vec![next, target],
+        }
+    }
+}
+
+/// Parsed with the default, extended instruction set.
+impl FromStr for Instruction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InstructionSet::default().parse_instruction(s)
+    }
+}
+
+/// The canonical form, as in the puzzle input: `acc +1`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code.name)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a program, one instruction per line.
+pub fn parse_program(contents: &str) -> Result<Vec<Instruction>, String> {
+    InstructionSet::default().parse_program(contents)
+}
+
+/// Why a `Machine` stopped running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    /// The instruction pointer reached the address just past the last
+    /// instruction, which is how a program exits normally.
+    Terminated,
+    /// The machine was about to run the instruction at `address` in exactly
+    /// the same state as before, so it would loop forever.
+    InfiniteLoop { address: usize },
+    /// The jump at `address` would go to `target`, which is neither a
+    /// program address nor the end of the program.
+    OutOfBounds { address: usize, target: i64 },
+    /// The instruction at `address` needs input. Running again after
+    /// `push_input` carries on from there.
+    WaitingForInput { address: usize },
+    /// The instruction at `address` would overflow a register.
+    Overflow { address: usize },
+    /// The machine has executed as many instructions as its step limit
+    /// allows, and was about to run the one at `address`.
+    StepLimit { address: usize },
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Halt::Terminated => write!(f, "terminated"),
+            Halt::InfiniteLoop { address } => write!(f, "infinite loop at address {}", address),
+            Halt::OutOfBounds { address, target } => write!(
+                f,
+                "jump out of bounds from address {} to {}",
+                address, target
+            ),
+            Halt::WaitingForInput { address } => {
+                write!(f, "waiting for input at address {}", address)
+            }
+            Halt::Overflow { address } => write!(f, "arithmetic overflow at address {}", address),
+            Halt::StepLimit { address } => write!(f, "step limit reached at address {}", address),
+        }
+    }
+}
+
+/// A running program, with its registers and instruction pointer.
+#[derive(Debug, Clone)]
+pub struct Machine {
+    program: Vec<Instruction>,
+    cpu: Cpu,
+    instruction_pointer: i32,
+    /// Whether any jump depends on the registers. If not, reaching an address
+    /// a second time is already enough to know the program loops.
+    branches: bool,
+    visited: Vec<bool>,
+    /// With branches, a state the machine loops forever if it gets back to,
+    /// renewed after 1, 2, 4, 8... steps as in Brent's cycle detection, so
+    /// memory stays the same however long the program runs. Forgotten when
+    /// input is read.
+    saved: Option<(i32, [i32; REGISTERS])>,
+    since_saved: usize,
+    wait: usize,
+    steps: usize,
+    step_limit: Option<usize>,
+}
+
+impl Machine {
+    pub fn new(program: Vec<Instruction>) -> Machine {
+        let visited = vec![false; program.len()];
+        let branches = program
+            .iter()
+            .any(|instruction| instruction.code.control == Control::Branch);
+        Machine {
+            program,
+            cpu: Cpu::new(),
+            instruction_pointer: 0,
+            branches,
+            visited,
+            saved: None,
+            since_saved: 0,
+            wait: 1,
+            steps: 0,
+            step_limit: None,
+        }
+    }
+
+    /// Stop with `Halt::StepLimit` after executing `limit` instructions, as a
+    /// watchdog for programs that run for too long without repeating a
+    /// state. `None`, the default, means no limit.
+    pub fn set_step_limit(&mut self, limit: Option<usize>) {
+        self.step_limit = limit;
+    }
+
+    /// The number of instructions executed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Start the program over from the beginning, with no input or output.
+    /// The step limit is kept.
+    pub fn reset(&mut self) {
+        self.cpu = Cpu::new();
+        self.instruction_pointer = 0;
+        self.steps = 0;
+        for visited in self.visited.iter_mut() {
+            *visited = false;
+        }
+        self.forget_state();
+    }
+
+    pub fn acc(&self) -> i32 {
+        self.cpu.registers[ACCUMULATOR]
+    }
+
+    pub fn register(&self, register: usize) -> i32 {
+        self.cpu.registers[register]
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn instruction_pointer(&self) -> i32 {
+        self.instruction_pointer
+    }
+
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
+    }
+
+    /// Queue a value for `in` instructions to read.
+    pub fn push_input(&mut self, value: i32) {
+        self.cpu.input.push_back(value);
+        // New input means old states can lead somewhere new
+        self.forget_state();
+    }
+
+    /// Everything written by `out` instructions so far.
+    pub fn output(&self) -> &[i32] {
+        &self.cpu.output
+    }
+
+    fn forget_state(&mut self) {
+        self.saved = None;
+        self.since_saved = 0;
+        self.wait = 1;
+    }
+
+    /// Whether the machine is about to repeat itself. With branches, a loop
+    /// is only noticed once the saved state is in it and the wait is at
+    /// least as long as the loop, so it can take a few times round first.
+    fn repeats_state(&self, address: usize) -> bool {
+        if self.branches {
+            self.since_saved > 0
+                && self.saved == Some((self.instruction_pointer, self.cpu.registers))
+        } else {
+            self.visited[address]
+        }
+    }
+
+    /// Execute the instruction at the instruction pointer.
+    ///
+    /// Returns `Some` without changing anything if the machine can't
+    /// continue: the program has ended, is about to repeat itself, needs more
+    /// input, or hit its step limit, or the instruction would jump out of
+    /// bounds or overflow.
+    pub fn step(&mut self) -> Option<Halt> {
+        // Jumps are checked before they're taken, so the instruction pointer
+        // is always an address or the end
+        let address = self.instruction_pointer as usize;
+        if address == self.program.len() {
+            return Some(Halt::Terminated);
+        }
+        if self.repeats_state(address) {
+            return Some(Halt::InfiniteLoop { address });
+        }
+        if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+            return Some(Halt::StepLimit { address });
+        }
+
+        let input_left = self.cpu.input.len();
+        let instruction = &self.program[address];
+        match (instruction.code.execute)(&mut self.cpu, &instruction.args) {
+            Action::Next => self.instruction_pointer += 1,
+            Action::Jump(offset) => {
+                let target = address as i64 + offset as i64;
+                if target < 0 || target > self.program.len() as i64 {
+                    return Some(Halt::OutOfBounds { address, target });
+                }
+                self.instruction_pointer = target as i32;
+            }
+            Action::WaitForInput => return Some(Halt::WaitingForInput { address }),
+            Action::Overflow => return Some(Halt::Overflow { address }),
+        }
+
+        self.steps += 1;
+        self.visited[address] = true;
+        if self.branches {
+            if self.cpu.input.len() != input_left {
+                self.forget_state();
+            }
+            self.since_saved += 1;
+            if self.saved.is_none() || self.since_saved == self.wait {
+                self.saved = Some((self.instruction_pointer, self.cpu.registers));
+                self.since_saved = 0;
+                self.wait *= 2;
+            }
+        }
+        None
+    }
+
+    /// Step until the machine halts.
+    pub fn run(&mut self) -> Halt {
+        loop {
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+}
+
+/// The program from the day 8 puzzle, shared by the tests of the console.
+#[cfg(test)]
+pub(crate) const SAMPLE_PROGRAM: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+#[cfg(test)]
+mod tests {
+    use super::isa::{ACC, JMP, NOP};
+    use super::*;
+
+    #[test]
+    fn test_build_instruction() {
+        assert_eq!(
+            "acc +1".parse::<Instruction>().unwrap(),
+            Instruction::new(ACC, vec![Operand::Value(1)])
+        );
+
+        assert_eq!(
+            "nop -1".parse::<Instruction>().unwrap(),
+            Instruction::new(NOP, vec![Operand::Value(-1)])
+        );
+
+        assert_eq!(
+            "jmp +4".parse::<Instruction>().unwrap(),
+            Instruction::new(JMP, vec![Operand::Value(4)])
+        );
+
+        assert!("jmp".parse::<Instruction>().is_err());
+        assert!("jmp four".parse::<Instruction>().is_err());
+        assert!("hcf +0".parse::<Instruction>().is_err());
+    }
+
+    #[test]
+    fn test_instruction_to_string() {
+        for line in SAMPLE_PROGRAM.lines() {
+            assert_eq!(line.parse::<Instruction>().unwrap().to_string(), line);
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        let mut machine = Machine::new(parse_program(SAMPLE_PROGRAM).unwrap());
+
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.step(), None);
+        assert_eq!((machine.acc(), machine.instruction_pointer()), (1, 2));
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.instruction_pointer(), 6);
+    }
+
+    #[test]
+    fn test_run_infinite_loop() {
+        let mut machine = Machine::new(parse_program(SAMPLE_PROGRAM).unwrap());
+
+        assert_eq!(machine.run(), Halt::InfiniteLoop { address: 1 });
+        assert_eq!(machine.acc(), 5);
+        // Stays halted
+        assert_eq!(machine.step(), Some(Halt::InfiniteLoop { address: 1 }));
+    }
+
+    #[test]
+    fn test_run_terminated() {
+        let mut machine = Machine::new(parse_program("acc +2\njmp +2\nacc +4\nacc -1").unwrap());
+
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.acc(), 1);
+    }
+
+    #[test]
+    fn test_run_out_of_bounds() {
+        let mut machine = Machine::new(parse_program("acc +2\njmp -2").unwrap());
+        assert_eq!(
+            machine.run(),
+            Halt::OutOfBounds {
+                address: 1,
+                target: -1
+            }
+        );
+        assert_eq!((machine.acc(), machine.instruction_pointer()), (2, 1));
+
+        let mut machine = Machine::new(parse_program("jmp +3\nnop +0").unwrap());
+        assert_eq!(
+            machine.run(),
+            Halt::OutOfBounds {
+                address: 0,
+                target: 3
+            }
+        );
+
+        // Further than an i32 can reach
+        let mut machine = Machine::new(parse_program("nop +0\njmp -2147483648").unwrap());
+        assert_eq!(
+            machine.run(),
+            Halt::OutOfBounds {
+                address: 1,
+                target: -2147483647
+            }
+        );
+    }
+
+    #[test]
+    fn test_run_overflow() {
+        let mut machine = Machine::new(parse_program("acc +2147483647\nacc +1").unwrap());
+        assert_eq!(machine.run(), Halt::Overflow { address: 1 });
+        assert_eq!(machine.acc(), i32::MAX);
+
+        let program = "set a -2147483648\nadd a -1";
+        let mut machine = Machine::new(parse_program(program).unwrap());
+        assert_eq!(machine.run(), Halt::Overflow { address: 1 });
+
+        let program = "set a +65536\nmul a a";
+        let mut machine = Machine::new(parse_program(program).unwrap());
+        assert_eq!(machine.run(), Halt::Overflow { address: 1 });
+        assert_eq!(machine.register(1), 65536);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        // Counts down from a thousand, which takes too long for the watchdog
+        let program = "set a +1000\nadd a -1\njnz a -1";
+        let mut machine = Machine::new(parse_program(program).unwrap());
+        machine.set_step_limit(Some(100));
+
+        assert_eq!(machine.run(), Halt::StepLimit { address: 2 });
+        assert_eq!(machine.steps(), 100);
+        machine.reset();
+        machine.set_step_limit(None);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.steps(), 2001);
+    }
+}
@@ -0,0 +1,386 @@
+//! Static analysis of console programs: the control-flow graph, split into
+//! basic blocks, and what can be said about a program without running it.
+//!
+//! Every instruction's successors only depend on its opcode and offset, so
+//! the graph is exact for programs without conditional jumps. A `jz` or
+//! `jnz` is assumed to go either way, which makes "unreachable" and "loops
+//! forever" conservative: they hold however the branches turn out.
+
+use super::isa::Control;
+use super::{Halt, Instruction};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Where control can go at the end of a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Block(usize),
+    /// The address just past the last instruction, where the program exits.
+    End,
+    /// Any other address outside the program.
+    OutOfBounds(i64),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Block(block) => write!(f, "block {}", block),
+            Target::End => write!(f, "end"),
+            Target::OutOfBounds(address) => write!(f, "out of bounds ({})", address),
+        }
+    }
+}
+
+/// A run of instructions that always execute together: only the first is
+/// ever jumped to, and only the last can jump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// One past the last address in the block.
+    pub end: usize,
+    pub targets: Vec<Target>,
+}
+
+/// The execution of a program without conditional jumps, which is the same
+/// every time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    /// Every address executed, in order, each at most once.
+    pub addresses: Vec<usize>,
+    /// How the program stops: `InfiniteLoop` gives the address it would
+    /// execute a second time.
+    pub halt: Halt,
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub blocks: Vec<BasicBlock>,
+    /// The block each address belongs to.
+    block_of: Vec<usize>,
+    reachable: Vec<bool>,
+    /// Whether some path from each address leaves the program, at the end or
+    /// out of bounds.
+    can_halt: Vec<bool>,
+    out_of_bounds: Vec<(usize, i64)>,
+    branches: bool,
+    successors: Vec<Vec<i64>>,
+}
+
+impl Analysis {
+    pub fn new(program: &[Instruction]) -> Analysis {
+        let len = program.len();
+        let successors: Vec<Vec<i64>> = program
+            .iter()
+            .enumerate()
+            .map(|(address, instruction)| instruction.successors(address))
+            .collect();
+        let in_program = |address: i64| address >= 0 && address < len as i64;
+
+        // A block starts at the entry, at every jump target and after every
+        // jump
+        let mut leader = vec![false; len];
+        if len > 0 {
+            leader[0] = true;
+        }
+        for (address, instruction) in program.iter().enumerate() {
+            if instruction.code.control == Control::Next {
+                continue;
+            }
+            for &next in &successors[address] {
+                if in_program(next) {
+                    leader[next as usize] = true;
+                }
+            }
+            if address + 1 < len {
+                leader[address + 1] = true;
+            }
+        }
+        let mut block_of = vec![0; len];
+        let mut starts = vec![];
+        for address in 0..len {
+            if leader[address] {
+                starts.push(address);
+            }
+            block_of[address] = starts.len() - 1;
+        }
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(block, &start)| {
+                let end = starts.get(block + 1).copied().unwrap_or(len);
+                let targets = successors[end - 1]
+                    .iter()
+                    .map(|&next| match next {
+                        next if in_program(next) => Target::Block(block_of[next as usize]),
+                        next if next == len as i64 => Target::End,
+                        next => Target::OutOfBounds(next),
+                    })
+                    .collect();
+                BasicBlock {
+                    start,
+                    end,
+                    targets,
+                }
+            })
+            .collect();
+
+        let out_of_bounds = successors
+            .iter()
+            .enumerate()
+            .flat_map(|(address, nexts)| {
+                nexts
+                    .iter()
+                    .filter(|&&next| next < 0 || next > len as i64)
+                    .map(move |&next| (address, next))
+            })
+            .collect();
+
+        // Forwards from the entry for reachability, backwards from every exit
+        // for whether halting is possible
+        let mut came_from: Vec<Vec<usize>> = vec![vec![]; len];
+        let mut exits = vec![];
+        for (address, nexts) in successors.iter().enumerate() {
+            for &next in nexts {
+                if in_program(next) {
+                    came_from[next as usize].push(address);
+                } else {
+                    exits.push(address);
+                }
+            }
+        }
+        let reachable = search(len, &[0], |address| {
+            successors[address]
+                .iter()
+                .filter(|&&next| in_program(next))
+                .map(|&next| next as usize)
+                .collect()
+        });
+        let can_halt = search(len, &exits, |address| came_from[address].clone());
+
+        Analysis {
+            blocks,
+            block_of,
+            reachable,
+            can_halt,
+            out_of_bounds,
+            branches: program
+                .iter()
+                .any(|instruction| instruction.code.control == Control::Branch),
+            successors,
+        }
+    }
+
+    pub fn block_of(&self, address: usize) -> usize {
+        self.block_of[address]
+    }
+
+    /// Addresses no execution can get to.
+    pub fn unreachable(&self) -> Vec<usize> {
+        (0..self.reachable.len())
+            .filter(|&address| !self.reachable[address])
+            .collect()
+    }
+
+    /// Every jump, as `(address, target)`, that leaves the program somewhere
+    /// other than its end, whether or not it can be reached.
+    pub fn out_of_bounds(&self) -> &[(usize, i64)] {
+        &self.out_of_bounds
+    }
+
+    /// Reachable addresses that, once executed, can never lead to the
+    /// program stopping.
+    pub fn traps(&self) -> Vec<usize> {
+        (0..self.reachable.len())
+            .filter(|&address| self.reachable[address] && !self.can_halt[address])
+            .collect()
+    }
+
+    /// Whether the program is certain to run forever. An empty program exits
+    /// straight away.
+    pub fn loops_forever(&self) -> bool {
+        !self.can_halt.is_empty() && !self.can_halt[0]
+    }
+
+    /// The one possible execution, for programs without conditional jumps.
+    pub fn path(&self) -> Option<Path> {
+        if self.branches {
+            return None;
+        }
+        let len = self.successors.len() as i64;
+        let mut visited = vec![false; self.successors.len()];
+        let mut addresses = vec![];
+        let mut address = 0;
+        let halt = loop {
+            if address == len {
+                break Halt::Terminated;
+            }
+            if visited[address as usize] {
+                break Halt::InfiniteLoop {
+                    address: address as usize,
+                };
+            }
+            visited[address as usize] = true;
+            addresses.push(address as usize);
+            let next = self.successors[address as usize][0];
+            if next < 0 || next > len {
+                break Halt::OutOfBounds {
+                    address: address as usize,
+                    target: next,
+                };
+            }
+            address = next;
+        };
+        Some(Path { addresses, halt })
+    }
+}
+
+/// Breadth-first search from `starts`, returning which of `len` addresses
+/// were found.
+fn search<F: Fn(usize) -> Vec<usize>>(len: usize, starts: &[usize], next: F) -> Vec<bool> {
+    let mut found = vec![false; len];
+    let mut queue = VecDeque::new();
+    for &start in starts {
+        if start < len && !found[start] {
+            found[start] = true;
+            queue.push_back(start);
+        }
+    }
+    while let Some(address) = queue.pop_front() {
+        for other in next(address) {
+            if !found[other] {
+                found[other] = true;
+                queue.push_back(other);
+            }
+        }
+    }
+    found
+}
+
+/// A report: the blocks and their edges, then anything suspicious.
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, block) in self.blocks.iter().enumerate() {
+            let targets: Vec<String> = block.targets.iter().map(Target::to_string).collect();
+            writeln!(
+                f,
+                "block {}: {:04}-{:04} -> {}",
+                index,
+                block.start,
+                block.end - 1,
+                targets.join(", ")
+            )?;
+        }
+        let list = |items: Vec<String>| {
+            if items.is_empty() {
+                String::from("none")
+            } else {
+                items.join(", ")
+            }
+        };
+        let addresses =
+            |addresses: Vec<usize>| list(addresses.iter().map(usize::to_string).collect());
+        writeln!(f, "unreachable: {}", addresses(self.unreachable()))?;
+        let jumps = self
+            .out_of_bounds
+            .iter()
+            .map(|(address, target)| format!("{} -> {}", address, target))
+            .collect();
+        writeln!(f, "out of bounds: {}", list(jumps))?;
+        writeln!(f, "never halts from: {}", addresses(self.traps()))?;
+        write!(
+            f,
+            "loops forever: {}",
+            if self.loops_forever() { "yes" } else { "no" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{parse_program, SAMPLE_PROGRAM};
+
+    fn block(start: usize, end: usize, targets: Vec<Target>) -> BasicBlock {
+        BasicBlock {
+            start,
+            end,
+            targets,
+        }
+    }
+
+    #[test]
+    fn test_basic_blocks() {
+        let analysis = Analysis::new(&parse_program(SAMPLE_PROGRAM).unwrap());
+
+        assert_eq!(
+            analysis.blocks,
+            vec![
+                block(0, 1, vec![Target::Block(1)]),
+                block(1, 3, vec![Target::Block(4)]),
+                block(3, 5, vec![Target::Block(1)]),
+                block(5, 6, vec![Target::Block(4)]),
+                block(6, 8, vec![Target::Block(2)]),
+                block(8, 9, vec![Target::End]),
+            ]
+        );
+        assert_eq!(analysis.block_of(7), 4);
+    }
+
+    #[test]
+    fn test_sample_analysis() {
+        let analysis = Analysis::new(&parse_program(SAMPLE_PROGRAM).unwrap());
+
+        assert_eq!(analysis.unreachable(), vec![5, 8]);
+        assert!(analysis.out_of_bounds().is_empty());
+        assert_eq!(analysis.traps(), vec![0, 1, 2, 3, 4, 6, 7]);
+        assert!(analysis.loops_forever());
+        assert_eq!(
+            analysis.path(),
+            Some(Path {
+                addresses: vec![0, 1, 2, 6, 7, 3, 4],
+                halt: Halt::InfiniteLoop { address: 1 }
+            })
+        );
+    }
+
+    #[test]
+    fn test_out_of_bounds_and_termination() {
+        let analysis = Analysis::new(&parse_program("acc +1\njmp +2\njmp -5\nnop +0").unwrap());
+
+        assert_eq!(analysis.out_of_bounds(), &[(2, -3)]);
+        assert_eq!(analysis.unreachable(), vec![2]);
+        assert!(!analysis.loops_forever());
+        assert_eq!(analysis.path().unwrap().halt, Halt::Terminated);
+        assert_eq!(analysis.blocks[0].targets, vec![Target::Block(2)]);
+        assert_eq!(analysis.blocks[2].targets, vec![Target::End]);
+    }
+
+    #[test]
+    fn test_branches() {
+        // No way out, whichever way the branch goes
+        let program = parse_program("set a +1\njnz a +0\nout a\njmp -1").unwrap();
+        let analysis = Analysis::new(&program);
+
+        assert_eq!(analysis.blocks[0].targets, vec![Target::Block(1)]);
+        assert_eq!(analysis.traps(), vec![0, 1, 2, 3]);
+        assert!(analysis.loops_forever());
+        assert_eq!(analysis.path(), None);
+
+        let analysis = Analysis::new(&parse_program("in a\njz a -1\nout a").unwrap());
+        assert_eq!(
+            analysis.blocks[0].targets,
+            vec![Target::Block(1), Target::Block(0)]
+        );
+        assert!(!analysis.loops_forever());
+        assert!(analysis.traps().is_empty());
+    }
+
+    #[test]
+    fn test_empty_program() {
+        let analysis = Analysis::new(&[]);
+
+        assert!(analysis.blocks.is_empty());
+        assert!(!analysis.loops_forever());
+        assert_eq!(analysis.path().unwrap().halt, Halt::Terminated);
+    }
+}
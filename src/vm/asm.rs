@@ -0,0 +1,253 @@
+//! A human friendly text format for console programs.
+//!
+//! The assembler accepts everything the puzzle input uses (`jmp -3`), plus:
+//!
+//! * blank lines,
+//! * comments, starting with `#` or `;` and running to the end of the line,
+//! * labels, written `name:` either on their own line or before an
+//!   instruction, and usable instead of a number for any offset operand (as
+//!   taken by `jmp`, `nop`, `jz` and `jnz`). They are resolved to the
+//!   relative offset the console expects.
+//!
+//! ```text
+//! # count down from three
+//!         acc +3
+//! loop:   acc -1
+//!         jmp loop    ; resolves to jmp -1
+//! ```
+
+use super::isa::{parse_operand, Control, InstructionSet, Opcode, OperandKind};
+use super::{Instruction, Operand};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A problem in assembler source, with the 1-based line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+enum Argument<'a> {
+    Operand(Operand),
+    Label(&'a str),
+}
+
+struct Statement<'a> {
+    line: usize,
+    code: Opcode,
+    args: Vec<Argument<'a>>,
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Assemble source text into a program, using the default instruction set.
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, AsmError> {
+    assemble_with(&InstructionSet::default(), source)
+}
+
+/// Assemble source text into a program made of instructions from `set`.
+pub fn assemble_with(set: &InstructionSet, source: &str) -> Result<Vec<Instruction>, AsmError> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut statements = vec![];
+
+    // First pass: find the address of every label
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| AsmError {
+            line: line_number,
+            message,
+        };
+        let mut text = line.split(['#', ';']).next().unwrap().trim();
+
+        if let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_label(label) {
+                return Err(error(format!("Invalid label {:?}", label)));
+            }
+            if labels.insert(label, statements.len()).is_some() {
+                return Err(error(format!(
+                    "Label {:?} is defined more than once",
+                    label
+                )));
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let mut words = text.split_whitespace();
+        let mnemonic = words.next().unwrap();
+        let code = set
+            .get(mnemonic)
+            .ok_or_else(|| error(format!("Invalid instruction {:?}", mnemonic)))?;
+        let words: Vec<&str> = words.collect();
+        if words.len() != code.operands.len() {
+            return Err(error(format!(
+                "{} takes {} operand(s)",
+                mnemonic,
+                code.operands.len()
+            )));
+        }
+        let args = words
+            .iter()
+            .zip(code.operands)
+            .map(|(word, kind)| match parse_operand(word, *kind) {
+                Some(operand) => Ok(Argument::Operand(operand)),
+                None if *kind == OperandKind::Offset && is_label(word) => Ok(Argument::Label(word)),
+                None => Err(error(format!(
+                    "Invalid operand {:?} for {}",
+                    word, mnemonic
+                ))),
+            })
+            .collect::<Result<Vec<Argument>, AsmError>>()?;
+        statements.push(Statement {
+            line: line_number,
+            code,
+            args,
+        });
+    }
+
+    // Second pass: turn labels into offsets relative to each instruction
+    statements
+        .iter()
+        .enumerate()
+        .map(|(address, statement)| {
+            let args = statement
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    Argument::Operand(operand) => Ok(*operand),
+                    Argument::Label(label) => match labels.get(label) {
+                        Some(target) => Ok(Operand::Value(*target as i32 - address as i32)),
+                        None => Err(AsmError {
+                            line: statement.line,
+                            message: format!("Undefined label {:?}", label),
+                        }),
+                    },
+                })
+                .collect::<Result<Vec<Operand>, AsmError>>()?;
+            Ok(Instruction::new(statement.code, args))
+        })
+        .collect()
+}
+
+/// List a program one instruction per line, in canonical form and prefixed
+/// with its address. Jumps are annotated with the address they go to.
+pub fn disassemble(program: &[Instruction]) -> String {
+    let width = program.len().to_string().len().max(4);
+    program
+        .iter()
+        .enumerate()
+        .map(|(address, instruction)| {
+            let line = format!("{:0width$}  {}", address, instruction, width = width);
+            match (instruction.code.control, instruction.offset()) {
+                (Control::Jump, Some(offset)) | (Control::Branch, Some(offset)) => format!(
+                    "{:<w$}; -> {}\n",
+                    line,
+                    address as i64 + offset as i64,
+                    w = width + 12
+                ),
+                _ => line + "\n",
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::parse_program;
+
+    #[test]
+    fn test_assemble_labels_and_comments() {
+        let source = "# count down from three
+
+        acc +3
+loop:   acc -1   ; takes one off
+        jmp loop
+done:
+        nop done";
+
+        assert_eq!(
+            assemble(source).unwrap(),
+            parse_program("acc +3\nacc -1\njmp -1\nnop +0").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_assemble_forward_label() {
+        let source = "jmp end\nacc +1\nend: acc +2";
+
+        assert_eq!(
+            assemble(source).unwrap(),
+            parse_program("jmp +2\nacc +1\nacc +2").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let line_of = |source: &str| assemble(source).unwrap_err().line;
+
+        assert_eq!(line_of("nop +0\nfoo +1"), 2);
+        assert_eq!(line_of("jmp"), 1);
+        assert_eq!(line_of("acc +1 +2"), 1);
+        assert_eq!(line_of("acc start\nstart: nop +0"), 1);
+        assert_eq!(line_of("nop +0\n\njmp nowhere"), 3);
+        assert_eq!(line_of("a:\na: nop +0"), 2);
+        assert_eq!(line_of("1x: nop +0"), 1);
+        assert_eq!(line_of("jz a"), 1);
+        assert_eq!(line_of("set +1 +2"), 1);
+    }
+
+    #[test]
+    fn test_assemble_extended() {
+        let source = "        set a +3
+loop:   add acc a
+        add a -1
+        jnz a loop";
+
+        assert_eq!(
+            assemble(source).unwrap(),
+            parse_program("set a +3\nadd acc a\nadd a -1\njnz a -2").unwrap()
+        );
+        assert_eq!(
+            disassemble(&assemble(source).unwrap()).lines().last(),
+            Some("0003  jnz a -2  ; -> 1")
+        );
+        assert!(assemble_with(&InstructionSet::day8(), source).is_err());
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = parse_program("nop +0\nacc +1\njmp +4\nacc -99").unwrap();
+
+        assert_eq!(
+            disassemble(&program),
+            "0000  nop +0
+0001  acc +1
+0002  jmp +4    ; -> 6
+0003  acc -99
+"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = parse_program("nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99").unwrap();
+        let source: Vec<String> = program.iter().map(Instruction::to_string).collect();
+
+        assert_eq!(assemble(&source.join("\n")).unwrap(), program);
+    }
+}
@@ -0,0 +1,301 @@
+//! An interactive debugger for console programs.
+//!
+//! Commands are read a line at a time, so the debugger can be driven from
+//! stdin or from a script:
+//!
+//! ```text
+//! step [n]        s    execute n instructions (default 1)
+//! continue        c    run until a breakpoint or the machine halts
+//! break <addr>    b    stop before executing <addr>
+//! delete <addr>   d    remove the breakpoint at <addr>
+//! breakpoints          list breakpoints
+//! registers       r    show acc, any other registers in use and the
+//!                      instruction pointer
+//! history [n]     h    the last n executed addresses (default all)
+//! list            l    the instructions around the instruction pointer
+//! reset                start the program over, keeping breakpoints
+//! help                 this list
+//! quit            q    leave the debugger
+//! ```
+
+use super::asm::disassemble;
+use super::{register_name, Halt, Machine, ACCUMULATOR, REGISTERS};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "step [n], continue, break <addr>, delete <addr>, breakpoints, registers, history [n], list, reset, help, quit";
+
+/// How many instructions `list` shows on either side of the instruction
+/// pointer.
+const LIST_CONTEXT: usize = 3;
+
+pub struct Debugger {
+    machine: Machine,
+    breakpoints: BTreeSet<usize>,
+    history: Vec<usize>,
+    halted: Option<Halt>,
+}
+
+impl Debugger {
+    pub fn new(machine: Machine) -> Debugger {
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            history: vec![],
+            halted: None,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// Addresses executed so far, oldest first.
+    pub fn history(&self) -> &[usize] {
+        &self.history
+    }
+
+    /// Read commands from `input` until it runs out or `quit`, writing
+    /// prompts and results to `output`.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "(dbg) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if let ["quit"] | ["q"] = words.as_slice() {
+                break;
+            }
+            let response = self.command(&words);
+            if !response.is_empty() {
+                writeln!(output, "{}", response)?;
+            }
+            write!(output, "(dbg) ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    /// Run a single command, returning what to print.
+    pub fn command(&mut self, words: &[&str]) -> String {
+        match words {
+            [] => String::new(),
+            ["step"] | ["s"] => self.step(1),
+            ["step", n] | ["s", n] => match n.parse() {
+                Ok(n) => self.step(n),
+                Err(_) => format!("Invalid count {:?}", n),
+            },
+            ["continue"] | ["c"] => self.resume(),
+            ["break", address] | ["b", address] => match address.parse::<usize>() {
+                Ok(address) if address < self.machine.program().len() => {
+                    self.breakpoints.insert(address);
+                    format!("Breakpoint at {}", address)
+                }
+                _ => format!("Invalid address {:?}", address),
+            },
+            ["delete", address] | ["d", address] => match address.parse::<usize>() {
+                Ok(address) if self.breakpoints.remove(&address) => {
+                    format!("Deleted breakpoint at {}", address)
+                }
+                _ => format!("No breakpoint at {:?}", address),
+            },
+            ["breakpoints"] => {
+                if self.breakpoints.is_empty() {
+                    String::from("No breakpoints")
+                } else {
+                    self.breakpoints
+                        .iter()
+                        .map(|address| address.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                }
+            }
+            ["registers"] | ["r"] => self.registers(),
+            ["history"] | ["h"] => self.format_history(self.history.len()),
+            ["history", n] | ["h", n] => match n.parse() {
+                Ok(n) => self.format_history(n),
+                Err(_) => format!("Invalid count {:?}", n),
+            },
+            ["list"] | ["l"] => self.list(),
+            ["reset"] => {
+                self.machine.reset();
+                self.history.clear();
+                self.halted = None;
+                String::from("Reset")
+            }
+            ["help"] => String::from(HELP),
+            _ => format!("Unknown command {:?}; try help", words.join(" ")),
+        }
+    }
+
+    /// Execute one instruction, recording it in the history.
+    fn execute(&mut self) -> Option<Halt> {
+        let address = self.machine.instruction_pointer();
+        let halt = self.machine.step();
+        if halt.is_none() {
+            self.history.push(address as usize);
+        }
+        self.halted = halt;
+        halt
+    }
+
+    fn step(&mut self, count: usize) -> String {
+        for _ in 0..count {
+            if let Some(halt) = self.execute() {
+                return format!("Halted: {}", halt);
+            }
+        }
+        self.location()
+    }
+
+    fn resume(&mut self) -> String {
+        // Always get past the instruction we're stopped at, even if it has a
+        // breakpoint
+        if let Some(halt) = self.execute() {
+            return format!("Halted: {}", halt);
+        }
+        loop {
+            let address = self.machine.instruction_pointer();
+            if address >= 0 && self.breakpoints.contains(&(address as usize)) {
+                return format!("Breakpoint at {}\n{}", address, self.location());
+            }
+            if let Some(halt) = self.execute() {
+                return format!("Halted: {}", halt);
+            }
+        }
+    }
+
+    /// The next instruction to execute, if any.
+    fn location(&self) -> String {
+        let address = self.machine.instruction_pointer();
+        if let Some(halt) = self.halted {
+            return format!("Halted: {}", halt);
+        }
+        match self.machine.program().get(address as usize) {
+            Some(instruction) if address >= 0 => format!("{}: {}", address, instruction),
+            _ => format!("{}: <end of program>", address),
+        }
+    }
+
+    /// `acc` and the instruction pointer, plus any other register that
+    /// isn't zero.
+    fn registers(&self) -> String {
+        let mut lines = vec![format!("acc {}", self.machine.acc())];
+        for register in ACCUMULATOR + 1..REGISTERS {
+            let value = self.machine.register(register);
+            if value != 0 {
+                lines.push(format!("{:<3} {}", register_name(register), value));
+            }
+        }
+        lines.push(format!("ip  {}", self.machine.instruction_pointer()));
+        lines.join("\n")
+    }
+
+    fn list(&self) -> String {
+        let program = self.machine.program();
+        let ip = self.machine.instruction_pointer();
+        let center = ip.max(0) as usize;
+        let start = center.saturating_sub(LIST_CONTEXT).min(program.len());
+        let end = (center + LIST_CONTEXT + 1).min(program.len());
+
+        disassemble(program)
+            .lines()
+            .enumerate()
+            .skip(start)
+            .take(end - start)
+            .map(|(address, line)| {
+                let marker = if address as i32 == ip { "=>" } else { "  " };
+                let breakpoint = if self.breakpoints.contains(&address) {
+                    "*"
+                } else {
+                    " "
+                };
+                format!("{}{} {}", marker, breakpoint, line)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn format_history(&self, count: usize) -> String {
+        let start = self.history.len().saturating_sub(count);
+        if self.history.is_empty() {
+            return String::from("Nothing executed yet");
+        }
+        self.history[start..]
+            .iter()
+            .map(|address| address.to_string())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{parse_program, SAMPLE_PROGRAM};
+
+    fn debug(script: &str) -> (Debugger, String) {
+        let mut debugger = Debugger::new(Machine::new(parse_program(SAMPLE_PROGRAM).unwrap()));
+        let mut output = vec![];
+        debugger.run(script.as_bytes(), &mut output).unwrap();
+        (debugger, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_step_and_registers() {
+        let (debugger, output) = debug("step 2\nregisters\nstep\nq\n");
+
+        assert_eq!(
+            output,
+            "(dbg) 2: jmp +4
+(dbg) acc 1
+ip  2
+(dbg) 6: acc +1
+(dbg) \n"
+        );
+        assert_eq!(debugger.history(), &[0, 1, 2]);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let (debugger, output) = debug("b 4\nc\nr\nc\nbreakpoints\nd 4\nc\nhistory 3\n");
+
+        assert_eq!(
+            output,
+            "(dbg) Breakpoint at 4
+(dbg) Breakpoint at 4
+4: jmp -3
+(dbg) acc 5
+ip  4
+(dbg) Halted: infinite loop at address 1
+(dbg) 4
+(dbg) Deleted breakpoint at 4
+(dbg) Halted: infinite loop at address 1
+(dbg) 7 3 4
+(dbg) \n"
+        );
+        assert_eq!(debugger.machine().acc(), 5);
+    }
+
+    #[test]
+    fn test_list_and_reset() {
+        let (debugger, output) = debug("b 1\ns 2\nlist\nreset\nh\nbogus\n");
+
+        assert_eq!(
+            output,
+            "(dbg) Breakpoint at 1
+(dbg) 2: jmp +4
+(dbg)     0000  nop +0
+  * 0001  acc +1
+=>  0002  jmp +4    ; -> 6
+    0003  acc +3
+    0004  jmp -3    ; -> 1
+    0005  acc -99
+(dbg) Reset
+(dbg) Nothing executed yet
+(dbg) Unknown command \"bogus\"; try help
+(dbg) \n"
+        );
+        assert_eq!(debugger.machine().instruction_pointer(), 0);
+    }
+}
@@ -0,0 +1,417 @@
+//! The console's instruction set, as a table of opcodes.
+//!
+//! Day 8 only needs `acc`, `jmp` and `nop`. The extended set adds arithmetic
+//! on the registers `a` to `z`, conditional jumps and integer I/O:
+//!
+//! ```text
+//! set r x      r = x
+//! add r x      r += x
+//! mul r x      r *= x
+//! jz x off     jump by off if x is zero
+//! jnz x off    jump by off if x is not zero
+//! in r         read the next input value into r
+//! out x        write x to the output
+//! ```
+//!
+//! where `r` is a register and `x` is a register or a literal. Puzzles that
+//! extend the console further can `register` their own `Opcode`s; parsing,
+//! the assembler and `Machine` all work from the table.
+
+use super::{Cpu, Instruction, Operand, ACCUMULATOR};
+
+/// What an opcode expects in each operand position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    /// A register, written to by the instruction.
+    Register,
+    /// A register or a literal, read by the instruction.
+    Source,
+    /// A literal offset from the instruction's own address. The assembler
+    /// accepts labels here.
+    Offset,
+}
+
+/// How an opcode can move the instruction pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Always continues with the next instruction.
+    Next,
+    /// Always jumps by its `Offset` operand.
+    Jump,
+    /// Continues or jumps by its `Offset` operand depending on the registers.
+    Branch,
+}
+
+/// What executing an instruction did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Next,
+    Jump(i32),
+    /// The instruction needs input that isn't there yet, and changed nothing.
+    WaitForInput,
+    /// The result doesn't fit in a register, so nothing was changed.
+    Overflow,
+}
+
+/// An entry in the instruction set table.
+#[derive(Clone, Copy)]
+pub struct Opcode {
+    pub name: &'static str,
+    pub operands: &'static [OperandKind],
+    pub control: Control,
+    /// Carry out the instruction, given operands matching `operands`.
+    pub execute: fn(&mut Cpu, &[Operand]) -> Action,
+}
+
+/// Opcodes are identified by name.
+impl PartialEq for Opcode {
+    fn eq(&self, other: &Opcode) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Opcode {}
+
+impl std::fmt::Debug for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Opcode({})", self.name)
+    }
+}
+
+pub const ACC: Opcode = Opcode {
+    name: "acc",
+    operands: &[OperandKind::Source],
+    control: Control::Next,
+    execute: acc,
+};
+
+pub const JMP: Opcode = Opcode {
+    name: "jmp",
+    operands: &[OperandKind::Offset],
+    control: Control::Jump,
+    execute: jmp,
+};
+
+/// Takes an offset it ignores, so a corrupted `jmp` can be swapped for it.
+pub const NOP: Opcode = Opcode {
+    name: "nop",
+    operands: &[OperandKind::Offset],
+    control: Control::Next,
+    execute: nop,
+};
+
+pub const SET: Opcode = Opcode {
+    name: "set",
+    operands: &[OperandKind::Register, OperandKind::Source],
+    control: Control::Next,
+    execute: set,
+};
+
+pub const ADD: Opcode = Opcode {
+    name: "add",
+    operands: &[OperandKind::Register, OperandKind::Source],
+    control: Control::Next,
+    execute: add,
+};
+
+pub const MUL: Opcode = Opcode {
+    name: "mul",
+    operands: &[OperandKind::Register, OperandKind::Source],
+    control: Control::Next,
+    execute: mul,
+};
+
+pub const JZ: Opcode = Opcode {
+    name: "jz",
+    operands: &[OperandKind::Source, OperandKind::Offset],
+    control: Control::Branch,
+    execute: jz,
+};
+
+pub const JNZ: Opcode = Opcode {
+    name: "jnz",
+    operands: &[OperandKind::Source, OperandKind::Offset],
+    control: Control::Branch,
+    execute: jnz,
+};
+
+pub const IN: Opcode = Opcode {
+    name: "in",
+    operands: &[OperandKind::Register],
+    control: Control::Next,
+    execute: input,
+};
+
+pub const OUT: Opcode = Opcode {
+    name: "out",
+    operands: &[OperandKind::Source],
+    control: Control::Next,
+    execute: output,
+};
+
+/// Store `value` in `register` if the arithmetic didn't overflow.
+fn store(cpu: &mut Cpu, register: Operand, value: Option<i32>) -> Action {
+    match value {
+        Some(value) => {
+            cpu.write(register, value);
+            Action::Next
+        }
+        None => Action::Overflow,
+    }
+}
+
+fn acc(cpu: &mut Cpu, args: &[Operand]) -> Action {
+    let acc = Operand::Register(ACCUMULATOR);
+    store(cpu, acc, cpu.read(acc).checked_add(cpu.read(args[0])))
+}
+
+fn jmp(_cpu: &mut Cpu, args: &[Operand]) -> Action {
+    Action::Jump(args[0].value())
+}
+
+fn nop(_cpu: &mut Cpu, _args: &[Operand]) -> Action {
+    Action::Next
+}
+
+fn set(cpu: &mut Cpu, args: &[Operand]) -> Action {
+    cpu.write(args[0], cpu.read(args[1]));
+    Action::Next
+}
+
+fn add(cpu: &mut Cpu, args: &[Operand]) -> Action {
+    store(
+        cpu,
+        args[0],
+        cpu.read(args[0]).checked_add(cpu.read(args[1])),
+    )
+}
+
+fn mul(cpu: &mut Cpu, args: &[Operand]) -> Action {
+    store(
+        cpu,
+        args[0],
+        cpu.read(args[0]).checked_mul(cpu.read(args[1])),
+    )
+}
+
+fn jz(cpu: &mut Cpu, args: &[Operand]) -> Action {
+    if cpu.read(args[0]) == 0 {
+        Action::Jump(args[1].value())
+    } else {
+        Action::Next
+    }
+}
+
+fn jnz(cpu: &mut Cpu, args: &[Operand]) -> Action {
+    if cpu.read(args[0]) != 0 {
+        Action::Jump(args[1].value())
+    } else {
+        Action::Next
+    }
+}
+
+fn input(cpu: &mut Cpu, args: &[Operand]) -> Action {
+    match cpu.input.pop_front() {
+        Some(value) => {
+            cpu.write(args[0], value);
+            Action::Next
+        }
+        None => Action::WaitForInput,
+    }
+}
+
+fn output(cpu: &mut Cpu, args: &[Operand]) -> Action {
+    let value = cpu.read(args[0]);
+    cpu.output.push(value);
+    Action::Next
+}
+
+/// The table of opcodes a program may use.
+#[derive(Debug, Clone)]
+pub struct InstructionSet {
+    opcodes: Vec<Opcode>,
+}
+
+impl InstructionSet {
+    /// Just the instructions from the day 8 puzzle.
+    pub fn day8() -> InstructionSet {
+        InstructionSet {
+            opcodes: vec![ACC, JMP, NOP],
+        }
+    }
+
+    /// Day 8 plus registers, arithmetic, conditional jumps and I/O.
+    pub fn extended() -> InstructionSet {
+        let mut set = InstructionSet::day8();
+        for opcode in &[SET, ADD, MUL, JZ, JNZ, IN, OUT] {
+            set.register(*opcode);
+        }
+        set
+    }
+
+    /// Add an opcode, replacing any existing one with the same name.
+    pub fn register(&mut self, opcode: Opcode) {
+        self.opcodes.retain(|o| o.name != opcode.name);
+        self.opcodes.push(opcode);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Opcode> {
+        self.opcodes.iter().find(|o| o.name == name).copied()
+    }
+
+    /// Parse one instruction, checking its operands against the opcode.
+    pub fn parse_instruction(&self, s: &str) -> Result<Instruction, String> {
+        let mut words = s.split_whitespace();
+        let name = words.next().ok_or("Empty instruction")?;
+        let code = self
+            .get(name)
+            .ok_or_else(|| format!("Invalid instruction {:?}", name))?;
+        let words: Vec<&str> = words.collect();
+        if words.len() != code.operands.len() {
+            return Err(format!(
+                "{} takes {} operand(s), got {:?}",
+                name,
+                code.operands.len(),
+                s
+            ));
+        }
+        let args = words
+            .iter()
+            .zip(code.operands)
+            .map(|(word, kind)| {
+                parse_operand(word, *kind).ok_or(format!("Invalid operand in {:?}", s))
+            })
+            .collect::<Result<Vec<Operand>, String>>()?;
+        Ok(Instruction { code, args })
+    }
+
+    /// Parse a program, one instruction per line.
+    pub fn parse_program(&self, contents: &str) -> Result<Vec<Instruction>, String> {
+        contents
+            .lines()
+            .map(|l| self.parse_instruction(l))
+            .collect()
+    }
+}
+
+/// The extended set, which runs day 8 programs unchanged.
+impl Default for InstructionSet {
+    fn default() -> Self {
+        InstructionSet::extended()
+    }
+}
+
+/// Parse an operand, if it is allowed in a position of the given kind.
+pub fn parse_operand(word: &str, kind: OperandKind) -> Option<Operand> {
+    let operand = word.parse::<Operand>().ok()?;
+    match (kind, operand) {
+        (OperandKind::Register, Operand::Register(_)) => Some(operand),
+        (OperandKind::Offset, Operand::Value(_)) => Some(operand),
+        (OperandKind::Source, _) => Some(operand),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{Halt, Machine};
+
+    #[test]
+    fn test_parse_with_registers() {
+        let set = InstructionSet::extended();
+
+        assert_eq!(
+            set.parse_instruction("jnz b -2").unwrap(),
+            Instruction {
+                code: JNZ,
+                args: vec![Operand::Register(2), Operand::Value(-2)]
+            }
+        );
+        assert!(set.parse_instruction("set 1 +2").is_err());
+        assert!(set.parse_instruction("jmp a").is_err());
+        assert!(set.parse_instruction("add a").is_err());
+        assert!(InstructionSet::day8()
+            .parse_instruction("mul a +2")
+            .is_err());
+    }
+
+    #[test]
+    fn test_factorial() {
+        let program = InstructionSet::extended()
+            .parse_program(
+                "in a
+set b +1
+jz a +4
+mul b a
+add a -1
+jmp -3
+out b",
+            )
+            .unwrap();
+        let mut machine = Machine::new(program);
+        machine.push_input(5);
+
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.output(), &[120]);
+    }
+
+    #[test]
+    fn test_wait_for_input() {
+        let program = InstructionSet::extended()
+            .parse_program("in a\nin b\nadd a b\nout a")
+            .unwrap();
+        let mut machine = Machine::new(program);
+        machine.push_input(2);
+
+        assert_eq!(machine.run(), Halt::WaitingForInput { address: 1 });
+        machine.push_input(40);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.output(), &[42]);
+    }
+
+    #[test]
+    fn test_loop_detection_with_branches() {
+        // Revisits addresses while counting down, then loops for real
+        let program = InstructionSet::extended()
+            .parse_program("set a +3\nadd a -1\njnz a -1\njmp +0")
+            .unwrap();
+        let mut machine = Machine::new(program);
+
+        assert_eq!(machine.run(), Halt::InfiniteLoop { address: 3 });
+        assert_eq!(machine.register(1), 0);
+    }
+
+    #[test]
+    fn test_long_countdown_terminates() {
+        // Every state is different, so none of them needs remembering
+        let program = InstructionSet::extended()
+            .parse_program("set a +300000\nadd a -1\njnz a -1")
+            .unwrap();
+        let mut machine = Machine::new(program);
+
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.steps(), 600_001);
+    }
+
+    #[test]
+    fn test_register_opcode() {
+        fn double(cpu: &mut Cpu, args: &[Operand]) -> Action {
+            cpu.write(args[0], cpu.read(args[0]) * 2);
+            Action::Next
+        }
+        let mut set = InstructionSet::extended();
+        set.register(Opcode {
+            name: "dbl",
+            operands: &[OperandKind::Register],
+            control: Control::Next,
+            execute: double,
+        });
+
+        let program = set.parse_program("set acc +21\ndbl acc").unwrap();
+        let mut machine = Machine::new(program);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.acc(), 42);
+        assert_eq!(machine.program()[1].to_string(), "dbl acc");
+    }
+}
@@ -0,0 +1,180 @@
+//! Fixing programs corrupted by a single `jmp`/`nop` swap, as in day 8 part
+//! 2, in linear time.
+//!
+//! Rather than trying every swap and rerunning the program, work out once
+//! which addresses already lead to the end of the program. Then follow the
+//! program from the start: the first `jmp` or `nop` on that path whose
+//! swapped version would land on such an address is the fix.
+//!
+//! Swapping an instruction can't break the path after it: if the path from
+//! the new target went back through the swapped instruction, the unswapped
+//! program would continue from there into the original loop and never reach
+//! the end.
+
+use super::isa::{Control, JMP, NOP};
+use super::{Halt, Instruction, Machine};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// The swap that makes a program terminate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+    pub address: usize,
+    pub original: Instruction,
+    pub replacement: Instruction,
+    /// The accumulator when the repaired program terminates.
+    pub acc: i32,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "changed {} at address {} to {}",
+            self.original, self.address, self.replacement
+        )
+    }
+}
+
+/// Where execution goes after `address`. Only used on programs without
+/// branches, so there is exactly one place.
+fn next_address(address: usize, instruction: &Instruction) -> i64 {
+    instruction.successors(address)[0]
+}
+
+fn swapped(instruction: &Instruction) -> Option<Instruction> {
+    let code = if instruction.code == JMP {
+        NOP
+    } else if instruction.code == NOP {
+        JMP
+    } else {
+        return None;
+    };
+    Some(Instruction::new(code, instruction.args.clone()))
+}
+
+/// For every address, whether running the program unchanged from there ends
+/// by terminating. Index `program.len()`, the end itself, is included.
+///
+/// Conditional jumps are treated as if they could go either way.
+pub fn reaches_end(program: &[Instruction]) -> Vec<bool> {
+    let end = program.len();
+    let mut came_from: Vec<Vec<usize>> = vec![vec![]; end + 1];
+    for (address, instruction) in program.iter().enumerate() {
+        for next in instruction.successors(address) {
+            if next >= 0 && next <= end as i64 {
+                came_from[next as usize].push(address);
+            }
+        }
+    }
+
+    let mut reaches = vec![false; end + 1];
+    reaches[end] = true;
+    let mut queue = VecDeque::new();
+    queue.push_back(end);
+    while let Some(address) = queue.pop_front() {
+        for &previous in &came_from[address] {
+            if !reaches[previous] {
+                reaches[previous] = true;
+                queue.push_back(previous);
+            }
+        }
+    }
+    reaches
+}
+
+/// Find the single `jmp`/`nop` swap that makes `program` terminate.
+pub fn repair(program: &[Instruction]) -> Result<Repair, &'static str> {
+    if program.iter().any(|i| i.code.control == Control::Branch) {
+        return Err("Can't repair programs with conditional jumps");
+    }
+    let reaches = reaches_end(program);
+    if reaches[0] {
+        return Err("Program already terminates");
+    }
+
+    let mut visited = vec![false; program.len()];
+    let mut address = 0;
+    // The unchanged program never terminates, so this stops at a loop or
+    // out of bounds jump
+    while address < program.len() && !visited[address] {
+        visited[address] = true;
+        let instruction = &program[address];
+        if let Some(replacement) = swapped(instruction) {
+            let next = next_address(address, &replacement);
+            if next >= 0 && next <= program.len() as i64 && reaches[next as usize] {
+                let mut repaired = program.to_vec();
+                repaired[address] = replacement.clone();
+                let mut machine = Machine::new(repaired);
+                // Reaching the end in the graph doesn't guarantee getting
+                // there: the run can still overflow or wait for input
+                if machine.run() == Halt::Terminated {
+                    return Ok(Repair {
+                        address,
+                        original: instruction.clone(),
+                        replacement,
+                        acc: machine.acc(),
+                    });
+                }
+            }
+        }
+        let next = next_address(address, instruction);
+        if next < 0 {
+            break;
+        }
+        address = next as usize;
+    }
+    Err("No single jmp/nop swap makes the program terminate")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{parse_program, SAMPLE_PROGRAM};
+
+    #[test]
+    fn test_reaches_end() {
+        let program = parse_program(SAMPLE_PROGRAM).unwrap();
+
+        assert_eq!(
+            reaches_end(&program),
+            vec![false, false, false, false, false, false, false, false, true, true]
+        );
+    }
+
+    #[test]
+    fn test_repair() {
+        let program = parse_program(SAMPLE_PROGRAM).unwrap();
+        let repair = repair(&program).unwrap();
+
+        assert_eq!(repair.address, 7);
+        assert_eq!(repair.replacement.to_string(), "nop -4");
+        assert_eq!(repair.acc, 8);
+        assert_eq!(repair.to_string(), "changed jmp -4 at address 7 to nop -4");
+    }
+
+    #[test]
+    fn test_repair_nop_to_jmp() {
+        // Only jumping over the loop at the start works
+        let program = parse_program("nop +3\nacc +1\njmp -1\nacc +2").unwrap();
+        let repair = repair(&program).unwrap();
+
+        assert_eq!((repair.address, repair.acc), (0, 2));
+    }
+
+    #[test]
+    fn test_repair_impossible() {
+        assert!(repair(&parse_program("acc +1\nacc +2").unwrap()).is_err());
+        assert!(repair(&parse_program("jmp +0\njmp -1").unwrap()).is_err());
+        assert!(repair(&parse_program("acc +1\njmp -1\njmp -2").unwrap()).is_err());
+        assert!(repair(&parse_program("jnz acc +0").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_repair_that_still_does_not_terminate() {
+        // The swap reaches the end in the graph, but the run overflows or
+        // waits for input on the way
+        assert!(repair(&parse_program("acc +2147483647\njmp +0\nacc +1").unwrap()).is_err());
+        assert!(repair(&parse_program("in a\njmp +0").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! Recording what a console program does as it runs: a trace of every
+//! executed instruction, and coverage counts per address.
+
+use super::{Halt, Instruction, Machine};
+use crate::json::Json;
+use std::fmt;
+
+/// One executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub address: usize,
+    pub instruction: Instruction,
+    pub acc_before: i32,
+    pub acc_after: i32,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}  {:<10}acc {} -> {}",
+            self.address,
+            self.instruction.to_string(),
+            self.acc_before,
+            self.acc_after
+        )
+    }
+}
+
+impl Step {
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            (String::from("address"), Json::from(self.address as i64)),
+            (
+                String::from("instruction"),
+                Json::from(self.instruction.to_string().as_str()),
+            ),
+            (
+                String::from("acc_before"),
+                Json::from(self.acc_before as i64),
+            ),
+            (String::from("acc_after"), Json::from(self.acc_after as i64)),
+        ])
+    }
+}
+
+/// Run the machine until it halts, calling `on_step` after every executed
+/// instruction.
+pub fn run_with<F: FnMut(&Step)>(machine: &mut Machine, mut on_step: F) -> Halt {
+    loop {
+        let address = machine.instruction_pointer();
+        let acc_before = machine.acc();
+        if let Some(halt) = machine.step() {
+            return halt;
+        }
+        let address = address as usize;
+        on_step(&Step {
+            address,
+            instruction: machine.program()[address].clone(),
+            acc_before,
+            acc_after: machine.acc(),
+        });
+    }
+}
+
+/// Everything a run executed, and why it stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    pub halt: Halt,
+}
+
+impl Trace {
+    /// Run the machine until it halts, recording every step.
+    pub fn record(machine: &mut Machine) -> Trace {
+        let mut steps = vec![];
+        let halt = run_with(machine, |step| steps.push(step.clone()));
+        Trace { steps, halt }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            (
+                String::from("steps"),
+                Json::Array(self.steps.iter().map(Step::to_json).collect()),
+            ),
+            (
+                String::from("halt"),
+                Json::from(self.halt.to_string().as_str()),
+            ),
+        ])
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        write!(f, "Halted: {}", self.halt)
+    }
+}
+
+/// How many times each instruction of a program ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub program: Vec<Instruction>,
+    pub counts: Vec<usize>,
+    pub halt: Halt,
+}
+
+impl Coverage {
+    pub fn from_trace(program: &[Instruction], trace: &Trace) -> Coverage {
+        let mut counts = vec![0; program.len()];
+        for step in &trace.steps {
+            counts[step.address] += 1;
+        }
+        Coverage {
+            program: program.to_vec(),
+            counts,
+            halt: trace.halt,
+        }
+    }
+
+    /// Addresses of the instructions that never ran.
+    pub fn never_executed(&self) -> Vec<usize> {
+        (0..self.counts.len())
+            .filter(|a| self.counts[*a] == 0)
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Json {
+        let instructions = self
+            .program
+            .iter()
+            .zip(&self.counts)
+            .enumerate()
+            .map(|(address, (instruction, count))| {
+                Json::Object(vec![
+                    (String::from("address"), Json::from(address as i64)),
+                    (
+                        String::from("instruction"),
+                        Json::from(instruction.to_string().as_str()),
+                    ),
+                    (String::from("count"), Json::from(*count as i64)),
+                ])
+            })
+            .collect();
+        let never_executed = self
+            .never_executed()
+            .into_iter()
+            .map(|address| Json::from(address as i64))
+            .collect();
+
+        Json::Object(vec![
+            (String::from("instructions"), Json::Array(instructions)),
+            (String::from("never_executed"), Json::Array(never_executed)),
+            (
+                String::from("halt"),
+                Json::from(self.halt.to_string().as_str()),
+            ),
+        ])
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "addr  count  instruction")?;
+        for (address, (instruction, count)) in self.program.iter().zip(&self.counts).enumerate() {
+            let marker = if *count == 0 { "  never executed" } else { "" };
+            writeln!(
+                f,
+                "{:04}  {:>5}  {:<10}{}",
+                address,
+                count,
+                instruction.to_string(),
+                marker
+            )?;
+        }
+        let executed = self.counts.len() - self.never_executed().len();
+        write!(
+            f,
+            "{} of {} instructions executed, halted: {}",
+            executed,
+            self.counts.len(),
+            self.halt
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{parse_program, SAMPLE_PROGRAM};
+
+    #[test]
+    fn test_trace() {
+        let mut machine = Machine::new(parse_program(SAMPLE_PROGRAM).unwrap());
+        let trace = Trace::record(&mut machine);
+
+        assert_eq!(trace.halt, Halt::InfiniteLoop { address: 1 });
+        assert_eq!(
+            trace
+                .steps
+                .iter()
+                .map(|s| s.address)
+                .collect::<Vec<usize>>(),
+            vec![0, 1, 2, 6, 7, 3, 4]
+        );
+        assert_eq!(trace.steps[5].to_string(), "0003  acc +3    acc 2 -> 5");
+        assert_eq!(
+            trace.steps[1].to_json().to_string(),
+            r#"{"address":1,"instruction":"acc +1","acc_before":0,"acc_after":1}"#
+        );
+        assert!(trace
+            .to_string()
+            .ends_with("Halted: infinite loop at address 1"));
+    }
+
+    #[test]
+    fn test_coverage() {
+        let program = parse_program(SAMPLE_PROGRAM).unwrap();
+        let trace = Trace::record(&mut Machine::new(program.clone()));
+        let coverage = Coverage::from_trace(&program, &trace);
+
+        assert_eq!(coverage.never_executed(), vec![5, 8]);
+        assert_eq!(
+            coverage.to_string().lines().nth(6),
+            Some("0005      0  acc -99     never executed")
+        );
+        assert_eq!(
+            coverage.to_string().lines().last(),
+            Some("7 of 9 instructions executed, halted: infinite loop at address 1")
+        );
+        assert_eq!(
+            coverage
+                .to_json()
+                .get("never_executed")
+                .unwrap()
+                .to_string(),
+            "[5,8]"
+        );
+    }
+}